@@ -1,28 +1,27 @@
-use core_foundation::runloop::CFRunLoop;
-
+use rmidi::error::MidiError;
 use rmidi::midi_con::*;
 
-fn main() {
-    let midi_con = ArcMutexMidiCon::new();
+fn main() -> Result<(), MidiError> {
+    let midi_con = ArcMutexMidiCon::new()?;
 
     println!("Sources: {:?}", midi_con.list_sources());
 
-    midi_con.connect_source_by_index(0, move |data, mc| {
+    midi_con.connect_source_by_index(0, move |_, data, mc| {
         println!("Received MIDI data from source 0: {:?}", data);
         println!("MIDI Connections state: {:?}", mc.list_sources());
-    });
+    })?;
 
-    midi_con.connect_source_by_index(1, move |data, mc| {
+    let source_1 = midi_con.connect_source_by_index(1, move |_, data, mc| {
         println!("Received MIDI data from source 1: {:?}", data);
         println!("MIDI Connections state: {:?}", mc.list_sources());
-    });
-
-    midi_con.connect_destination_by_index(0);
-    midi_con.send(0, &[0xc0, 0x03]); // Program Change to program 2
+    })?;
 
-    midi_con.disconnect_source(1);
+    let destination = midi_con.connect_destination_by_index(0)?;
+    midi_con.send(destination, &[0xc0, 0x03])?; // Program Change to program 2
 
-    CFRunLoop::run_current();
+    midi_con.disconnect_source(source_1)?;
 
-    loop {}
+    loop {
+        std::thread::park();
+    }
 }
@@ -1,4 +1,5 @@
-use core_foundation::runloop::CFRunLoop;
+use rmidi::channel::Receiver;
+use rmidi::message::{MidiMessage, MidiParser};
 use rmidi::midi_con::*;
 use std::{
     collections::HashMap,
@@ -27,11 +28,6 @@ fn main() -> eframe::Result {
         options,
         Box::new(|_cc| Ok(Box::new(app))),
     )
-    .unwrap();
-
-    CFRunLoop::run_current();
-
-    loop {}
 }
 
 enum Learn {
@@ -41,19 +37,23 @@ enum Learn {
 }
 
 struct MidiMapper {
-    previous: Option<Vec<u8>>,
-    next: Option<Vec<u8>>,
+    previous: Option<MidiMessage>,
+    next: Option<MidiMessage>,
 }
+#[allow(dead_code)] // settings not yet implemented
 struct App {
     midi_con: ArcMutexMidiCon,
     channels: [bool; 7],
     selected_channel: usize,
     show_settings: bool,
-    sources: Vec<(usize, bool, String)>,
-    destinations: Vec<(usize, bool, String)>,
+    sources: Vec<(EndpointId, bool, String)>,
+    destinations: Vec<(EndpointId, bool, String)>,
+    destination: Option<EndpointId>,
     selected_source: usize,
     learn: Learn,
-    midi_map: HashMap<usize, MidiMapper>,
+    midi_map: HashMap<EndpointId, MidiMapper>,
+    /// Connected sources, polled once per frame
+    inputs: HashMap<EndpointId, (Receiver, MidiParser)>,
 }
 
 #[derive(Clone)]
@@ -62,11 +62,28 @@ struct ArcMutexApp(Arc<Mutex<App>>);
 impl ArcMutexApp {
     fn notification_callback(&self, notification: &Notification) {
         println!("App received notification: {:?}", notification);
+        match notification {
+            Notification::SourceAdded { .. } | Notification::SourceRemoved { .. } => {
+                let app = &mut *self.0.lock().unwrap();
+                app.sources = app.midi_con.list_sources();
+            }
+            Notification::DestinationAdded { .. } | Notification::DestinationRemoved { .. } => {
+                let app = &mut *self.0.lock().unwrap();
+                app.destinations = app.midi_con.list_destinations();
+            }
+            _ => {}
+        }
     }
 
     fn new() -> Self {
-        let midi_con = ArcMutexMidiCon::new();
-        midi_con.connect_destination_by_index(0);
+        let midi_con = ArcMutexMidiCon::new().expect("Failed to open MIDI");
+        let destination = match midi_con.connect_destination_by_index(0) {
+            Ok(destination) => Some(destination),
+            Err(err) => {
+                println!("Failed to connect destination: {}", err);
+                None
+            }
+        };
 
         let sources = midi_con.list_sources();
         let destinations = midi_con.list_destinations();
@@ -78,22 +95,137 @@ impl ArcMutexApp {
             show_settings: false,
             sources,
             destinations,
+            destination,
             selected_source: 0,
             learn: Learn::None,
             midi_map: HashMap::new(),
+            inputs: HashMap::new(),
         })))
     }
 }
 
 impl App {
     fn send_program_change(&self) {
-        self.midi_con.send(0, &[0xc0, self.selected_channel as u8]); // Program Change to program channel
+        // Program Change to program channel
+        match MidiMessage::program_change(1, self.selected_channel as u8) {
+            Ok(message) => self.send_message(&message),
+            Err(err) => println!("Invalid program change: {}", err),
+        }
+    }
+
+    fn select_previous_channel(&mut self) {
+        let mut next = self.selected_channel;
+        loop {
+            next = (next + self.channels.len() - 1) % self.channels.len();
+            if next == self.selected_channel || self.channels[next] {
+                break; // No other channels available
+            }
+        }
+        self.selected_channel = next;
+        self.send_program_change();
+        println!("Selected channel: {}", self.selected_channel);
+    }
+
+    fn select_next_channel(&mut self) {
+        let mut next = self.selected_channel;
+        loop {
+            next = (next + 1) % self.channels.len();
+            if next == self.selected_channel || self.channels[next] {
+                break; // No other channels available
+            }
+        }
+        self.selected_channel = next;
+        self.send_program_change();
+        println!("Selected channel: {}", self.selected_channel);
+    }
+
+    /// Handle the data received since the last frame
+    fn poll_inputs(&mut self) {
+        let mut data = vec![];
+        let mut messages = vec![];
+        for (receiver, parser) in self.inputs.values_mut() {
+            for event in receiver.try_iter() {
+                println!(
+                    "Received MIDI data from source {:?}: {:?}",
+                    event.source, event.data
+                );
+                parser.parse(&event.data, |message| {
+                    messages.push((event.source, message))
+                });
+                data.push(event.data);
+            }
+        }
+        for data in data {
+            self.send(&data);
+        }
+        for (source, message) in messages {
+            self.receive(source, message);
+        }
+    }
+
+    /// Handle a message received from `source`
+    fn receive(&mut self, source: EndpointId, message: MidiMessage) {
+        if message.is_realtime() {
+            return; // clock and active sensing are not mapped
+        }
+        match self.learn {
+            Learn::None => {
+                // Normal MIDI handling
+                let Some(map) = self.midi_map.get(&source) else {
+                    return;
+                };
+                let (previous, next) = (
+                    map.previous.as_ref() == Some(&message),
+                    map.next.as_ref() == Some(&message),
+                );
+                if previous {
+                    self.select_previous_channel();
+                }
+                if next {
+                    self.select_next_channel();
+                }
+            }
+            Learn::PreviousChannel => {
+                println!("Learn Previous Channel: {:?}", message);
+                self.mapper(source).previous = Some(message);
+                self.learn = Learn::None;
+            }
+            Learn::NextChannel => {
+                println!("Learn Next Channel: {:?}", message);
+                self.mapper(source).next = Some(message);
+                self.learn = Learn::None;
+            }
+        }
+    }
+
+    fn mapper(&mut self, source: EndpointId) -> &mut MidiMapper {
+        self.midi_map.entry(source).or_insert(MidiMapper {
+            previous: None,
+            next: None,
+        })
+    }
+
+    fn send_message(&self, message: &MidiMessage) {
+        if let Some(destination) = self.destination
+            && let Err(err) = self.midi_con.send_message(destination, message)
+        {
+            println!("Failed to send MIDI message: {}", err);
+        }
+    }
+
+    fn send(&self, data: &[u8]) {
+        if let Some(destination) = self.destination
+            && let Err(err) = self.midi_con.send(destination, data)
+        {
+            println!("Failed to send MIDI data: {}", err);
+        }
     }
 }
 
 impl eframe::App for ArcMutexApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let app = &mut *self.0.lock().unwrap();
+        app.poll_inputs();
         ctx.request_repaint();
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -125,99 +257,23 @@ impl eframe::App for ArcMutexApp {
                     for (i, connected, name) in app.sources.iter_mut() {
                         if ui.checkbox(&mut *connected, "").changed() {
                             if *connected {
-                                let source_index = *i;
-                                let cb_app = self.clone();
-                                app.midi_con.connect_source_by_index(
-                                    source_index,
-                                    move |data, midi_con| {
-                                        let app = &mut *cb_app.0.lock().unwrap();
-                                        println!(
-                                            "Received MIDI data from source {}: {:?}",
-                                            source_index, data
-                                        );
-                                        midi_con.send(0, data);
-                                        match app.learn {
-                                            Learn::None => {
-                                                // Normal MIDI handling
-                                                if let Some(map) = app.midi_map.get(&source_index) {
-                                                    if let Some(prev) = &map.previous {
-                                                        if data == prev.as_slice() {
-                                                            // Previous channel
-                                                            let mut next = app.selected_channel;
-                                                            loop {
-                                                                next = (next + app.channels.len()
-                                                                    - 1)
-                                                                    % app.channels.len();
-                                                                if next == app.selected_channel
-                                                                    || app.channels[next]
-                                                                {
-                                                                    break; // No other channels available
-                                                                }
-                                                            }
-                                                            app.selected_channel = next;
-                                                            app.send_program_change();
-                                                            println!(
-                                                                "Selected channel: {}",
-                                                                app.selected_channel
-                                                            );
-                                                        }
-                                                    }
-                                                    if let Some(next) = &map.next {
-                                                        if data == next.as_slice() {
-                                                            // Next channel
-                                                            let mut next = app.selected_channel;
-                                                            loop {
-                                                                next =
-                                                                    (next + 1) % app.channels.len();
-                                                                if next == app.selected_channel
-                                                                    || app.channels[next]
-                                                                {
-                                                                    break; // No other channels available
-                                                                }
-                                                            }
-                                                            app.selected_channel = next;
-                                                            app.send_program_change();
-                                                            println!(
-                                                                "Selected channel: {}",
-                                                                app.selected_channel
-                                                            );
-                                                            return;
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            _ => {
-                                                // MIDI Learn logic
-                                                let v = data.to_vec();
-                                                let map = app
-                                                    .midi_map
-                                                    .entry(source_index)
-                                                    .or_insert(MidiMapper {
-                                                        previous: None,
-                                                        next: None,
-                                                    });
-
-                                                match app.learn {
-                                                    Learn::PreviousChannel => {
-                                                        println!("Learn Previous Channel");
-                                                        map.previous = Some(v);
-                                                    }
-                                                    Learn::NextChannel => {
-                                                        println!("Learn Next Channel");
-                                                        map.next = Some(v);
-                                                    }
-                                                    _ => unreachable!(),
-                                                }
-                                                app.learn = Learn::None;
-                                            }
-                                        }
-                                    },
-                                );
+                                match app.midi_con.connect_source_channel(*i) {
+                                    Ok(receiver) => {
+                                        app.inputs.insert(*i, (receiver, MidiParser::new()));
+                                    }
+                                    Err(err) => {
+                                        println!("Failed to connect source: {}", err);
+                                        *connected = false;
+                                    }
+                                }
                             } else {
-                                app.midi_con.disconnect_source(*i);
+                                app.inputs.remove(i);
+                                if let Err(err) = app.midi_con.disconnect_source(*i) {
+                                    println!("Failed to disconnect source: {}", err);
+                                }
                             }
                         }
-                        ui.label(format!("{}", name));
+                        ui.label(name.as_str());
 
                         ui.end_row();
                     }
@@ -249,16 +305,7 @@ impl eframe::App for ArcMutexApp {
                         println!("Learn <<<");
                         app.learn = Learn::PreviousChannel;
                     } else {
-                        let mut next = app.selected_channel;
-                        loop {
-                            next = (next + app.channels.len() - 1) % app.channels.len();
-                            if next == app.selected_channel || app.channels[next] {
-                                break; // No other channels available
-                            }
-                        }
-                        app.selected_channel = next;
-                        app.send_program_change();
-                        println!("Selected channel: {}", app.selected_channel);
+                        app.select_previous_channel();
                     }
                 }
 
@@ -268,16 +315,7 @@ impl eframe::App for ArcMutexApp {
                         println!("Learn >>>");
                         app.learn = Learn::NextChannel;
                     } else {
-                        let mut next = app.selected_channel;
-                        loop {
-                            next = (next + 1) % app.channels.len();
-                            if next == app.selected_channel || app.channels[next] {
-                                break; // No other channels available
-                            }
-                        }
-                        app.selected_channel = next;
-                        app.send_program_change();
-                        println!("Selected channel: {}", app.selected_channel);
+                        app.select_next_channel();
                     }
                 }
             });
@@ -1,5 +1,4 @@
-use core_foundation::runloop::CFRunLoop;
-
+use rmidi::error::MidiError;
 use rmidi::midi_con::*;
 
 struct App {}
@@ -14,23 +13,24 @@ impl App {
     }
 }
 
-fn main() {
+fn main() -> Result<(), MidiError> {
     let app = App::new();
-    let midi_con = ArcMutexMidiCon::new();
+    let midi_con = ArcMutexMidiCon::new()?;
     midi_con.set_notification_callback(move |notification: &Notification| {
         app.notification_callback(notification);
     });
 
     println!("Sources: {:?}", midi_con.list_sources());
 
-    midi_con.connect_source_by_index(0, move |data, mc| {
+    midi_con.connect_source_by_index(0, move |_, data, mc| {
         println!("Received MIDI data from source 0: {:?}", data);
         println!("MIDI Connections state: {:?}", mc.list_sources());
-    });
+    })?;
 
-    midi_con.connect_destination_by_index(0);
-    midi_con.send(0, &[0xc0, 0x03]); // Program Change to program 2
+    let destination = midi_con.connect_destination_by_index(0)?;
+    midi_con.send(destination, &[0xc0, 0x03])?; // Program Change to program 2
 
-    CFRunLoop::run_current();
-    loop {}
+    loop {
+        std::thread::park();
+    }
 }
@@ -1,28 +1,24 @@
-use std::mem::MaybeUninit;
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
 
-use windows::Win32::Media::Audio::{HMIDIIN, MIDI_WAVE_OPEN_TYPE};
-use windows::Win32::Media::Audio::{midiInGetNumDevs, midiInOpen};
-fn main() {
-    let mut h: MaybeUninit<HMIDIIN> = MaybeUninit::uninit();
-    println!("Connected MIDI In Devices: {}", unsafe {
-        midiInGetNumDevs()
-    });
-    unsafe {
-        midiInOpen(
-            h.as_mut_ptr(),
-            0,
-            Option::Some(&mut callback as *mut _ as usize),
-            None,
-            MIDI_WAVE_OPEN_TYPE(0),
+fn main() -> Result<(), MidiError> {
+    let midi_con = ArcMutexMidiCon::new()?;
+
+    println!("Sources: {:?}", midi_con.list_sources());
+    println!("Destinations: {:?}", midi_con.list_destinations());
+
+    midi_con.connect_source_by_index(0, move |timestamp, data, _mc| {
+        println!(
+            "Received MIDI data from source 0 at {:?}: {:?}",
+            timestamp, data
         );
-    }
-}
+    })?;
+
+    let destination = midi_con.connect_destination_by_index(0)?;
+    midi_con.send(destination, &[0xc0, 0x03])?; // Program Change to program 2
+    midi_con.send(destination, &[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7])?; // Identity Request
 
-extern "C" fn callback(
-    _h_midi_in: HMIDIIN,
-    _w_msg: usize,
-    _dw_instance: usize,
-    _dw_param1: usize,
-    _dw_param2: usize,
-) {
+    loop {
+        std::thread::park();
+    }
 }
@@ -0,0 +1,157 @@
+use crate::error::MidiError;
+use crate::midi_con::{EndpointId, EndpointInfo, Notification};
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Callback invoked by a backend for each chunk of incoming MIDI data, with the
+/// time it was received, see [`MidiBackend::now`].
+pub type InputCallback = Box<dyn FnMut(Duration, &[u8]) + Send + 'static>;
+
+/// Callback invoked by a backend when the MIDI setup changes.
+///
+/// It may be invoked from any thread, and by the facade, see
+/// [`ArcMutexMidiCon::set_notification_callback`](crate::midi_con::ArcMutexMidiCon::set_notification_callback),
+/// from several threads at once.
+pub type NotificationHandler = Box<dyn Fn(&Notification) + Send + Sync + 'static>;
+
+/// The platform specific part of a MIDI connection.
+///
+/// Sources and destinations are identified by an [`EndpointId`], which stays the
+/// same while the endpoint exists, regardless of other endpoints coming and going.
+pub trait MidiBackend: Send {
+    /// Ids and names of the currently available MIDI sources, in enumeration order.
+    fn sources(&self) -> Vec<(EndpointId, String)>;
+
+    /// Ids and names of the currently available MIDI destinations, in enumeration order.
+    fn destinations(&self) -> Vec<(EndpointId, String)>;
+
+    /// Properties of the currently available MIDI sources, in enumeration
+    /// order. Defaults to the names of [`sources`](Self::sources).
+    fn source_info(&self) -> Vec<EndpointInfo> {
+        self.sources()
+            .into_iter()
+            .map(|(id, name)| EndpointInfo::new(id, name))
+            .collect()
+    }
+
+    /// Properties of the currently available MIDI destinations, in
+    /// enumeration order. Defaults to the names of
+    /// [`destinations`](Self::destinations).
+    fn destination_info(&self) -> Vec<EndpointInfo> {
+        self.destinations()
+            .into_iter()
+            .map(|(id, name)| EndpointInfo::new(id, name))
+            .collect()
+    }
+
+    /// Time elapsed since the session epoch, the creation of the backend.
+    ///
+    /// This is the timebase of the timestamps of incoming data, converted from
+    /// the host time of the platform where available.
+    fn now(&self) -> Duration;
+
+    /// Install the handler called when sources or destinations change.
+    fn set_notification_handler(&mut self, handler: NotificationHandler);
+
+    /// Open the `source`, delivering incoming data to `callback`.
+    ///
+    /// The source stays connected until the returned connection is dropped.
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError>;
+
+    /// Open the `destination` for sending.
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError>;
+
+    /// Create a virtual source named `name`, which other applications can
+    /// connect to, returning its id and the connection to send from.
+    ///
+    /// The source is removed when the returned connection is dropped.
+    fn create_virtual_source(
+        &mut self,
+        _name: &str,
+    ) -> Result<(EndpointId, Box<dyn OutputConnection>), MidiError> {
+        Err(MidiError::Unsupported("virtual source"))
+    }
+
+    /// Create a virtual destination named `name`, which other applications can
+    /// send to, delivering incoming data to `callback`.
+    ///
+    /// The destination is removed when the returned connection is dropped.
+    fn create_virtual_destination(
+        &mut self,
+        _name: &str,
+        _callback: InputCallback,
+    ) -> Result<(EndpointId, Box<dyn InputConnection>), MidiError> {
+        Err(MidiError::Unsupported("virtual destination"))
+    }
+}
+
+/// An open MIDI source, disconnected on drop.
+pub trait InputConnection: Send {}
+
+/// An open MIDI destination, disconnected on drop.
+pub trait OutputConnection: Send {
+    /// Send raw MIDI data to the destination.
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError>;
+
+    /// Whether the connection schedules data natively, see
+    /// [`OutputConnection::send_at`]. Otherwise, data is scheduled in software.
+    fn schedules(&self) -> bool {
+        false
+    }
+
+    /// Schedule raw MIDI data for delivery at `timestamp`, see
+    /// [`MidiBackend::now`]. Only called if the connection
+    /// [schedules](OutputConnection::schedules).
+    fn send_at(&mut self, _timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        self.send(data)
+    }
+
+    /// Drop data scheduled by [`OutputConnection::send_at`] not yet delivered.
+    fn flush(&mut self) -> Result<(), MidiError> {
+        Ok(())
+    }
+}
+
+/// Channel handing the [`NotificationHandler`] installed on a backend over to
+/// the thread delivering the notifications of the platform.
+pub(crate) fn notification_channel() -> (HandlerSender, HandlerReceiver) {
+    let (tx, rx) = mpsc::channel();
+    (HandlerSender(tx), HandlerReceiver { rx, handler: None })
+}
+
+/// The backend's end of a [`notification_channel`].
+pub(crate) struct HandlerSender(mpsc::Sender<NotificationHandler>);
+
+impl HandlerSender {
+    /// Replace the handler, dropped if the notifying thread is gone, as there
+    /// is nothing left to notify then.
+    pub(crate) fn install(&self, handler: NotificationHandler) {
+        let _ = self.0.send(handler);
+    }
+}
+
+/// The notifying thread's end of a [`notification_channel`].
+pub(crate) struct HandlerReceiver {
+    rx: mpsc::Receiver<NotificationHandler>,
+    handler: Option<NotificationHandler>,
+}
+
+impl HandlerReceiver {
+    /// Call the handler installed last, if any.
+    pub(crate) fn notify(&mut self, notification: &Notification) {
+        while let Ok(handler) = self.rx.try_recv() {
+            self.handler = Some(handler);
+        }
+        if let Some(handler) = &self.handler {
+            handler(notification);
+        }
+    }
+}
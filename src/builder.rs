@@ -0,0 +1,126 @@
+//! Configuration of [`ArcMutexMidiCon`] before it is created.
+//!
+//! Options set through [`MidiConBuilder`] are in effect from the start,
+//! notably the notification callback, which would otherwise miss the
+//! notifications of the backend arriving before
+//! [`ArcMutexMidiCon::set_notification_callback`] is called.
+
+use crate::backend::{MidiBackend, NotificationHandler};
+use crate::channel::Delivery;
+use crate::error::MidiError;
+use crate::midi_con::{ArcMutexMidiCon, Notification};
+use crate::sysex::SysExLimits;
+
+use std::sync::Arc;
+
+/// Builder of [`ArcMutexMidiCon`], see the [module](self) documentation.
+///
+/// ```no_run
+/// use rmidi::builder::MidiConBuilder;
+///
+/// let midi_con = MidiConBuilder::new()
+///     .client_name("My Sequencer")
+///     .notification_callback(|notification| println!("{notification:?}"))
+///     .build()?;
+/// # Ok::<(), rmidi::error::MidiError>(())
+/// ```
+pub struct MidiConBuilder {
+    client_name: String,
+    opt_notification_callback: Option<Arc<NotificationHandler>>,
+    opt_backend: Option<Box<dyn MidiBackend>>,
+    sysex_limits: SysExLimits,
+    delivery: Delivery,
+}
+
+impl Default for MidiConBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiConBuilder {
+    /// Builder of the native backend of the platform, with the client name
+    /// "rmidi" and default settings otherwise.
+    pub fn new() -> Self {
+        MidiConBuilder {
+            client_name: "rmidi".to_string(),
+            opt_notification_callback: None,
+            opt_backend: None,
+            sysex_limits: SysExLimits::default(),
+            delivery: Delivery::default(),
+        }
+    }
+
+    /// Name of the client of the native backend, as shown to other
+    /// applications. Ignored by [`backend`](Self::backend).
+    pub fn client_name(mut self, client_name: &str) -> Self {
+        self.client_name = client_name.to_string();
+        self
+    }
+
+    /// Callback for notifications, see
+    /// [`ArcMutexMidiCon::set_notification_callback`].
+    pub fn notification_callback(
+        mut self,
+        cb: impl Fn(&Notification) + Send + Sync + 'static,
+    ) -> Self {
+        self.opt_notification_callback = Some(Arc::new(Box::new(cb)));
+        self
+    }
+
+    /// Backend to use instead of the native backend of the platform, e.g.,
+    /// [`MockBackend`](crate::mock::MockBackend).
+    pub fn backend(mut self, backend: Box<dyn MidiBackend>) -> Self {
+        self.opt_backend = Some(backend);
+        self
+    }
+
+    /// Limits of SysEx messages reassembled from incoming data, see
+    /// [`ArcMutexMidiCon::set_sysex_limits`].
+    pub fn sysex_limits(mut self, sysex_limits: SysExLimits) -> Self {
+        self.sysex_limits = sysex_limits;
+        self
+    }
+
+    /// Delivery of sources connected through a channel, see
+    /// [`ArcMutexMidiCon::set_delivery`].
+    pub fn delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// Create the MidiConnections, opening the native backend unless a backend
+    /// was given.
+    pub fn build(self) -> Result<ArcMutexMidiCon, MidiError> {
+        let backend = match self.opt_backend {
+            Some(backend) => backend,
+            None => native_backend(&self.client_name)?,
+        };
+        Ok(ArcMutexMidiCon::with_settings(
+            backend,
+            self.opt_notification_callback,
+            self.sysex_limits,
+            self.delivery,
+        ))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn native_backend(client_name: &str) -> Result<Box<dyn MidiBackend>, MidiError> {
+    Ok(Box::new(crate::macos::CoreMidiBackend::new(client_name)?))
+}
+
+#[cfg(target_os = "linux")]
+fn native_backend(client_name: &str) -> Result<Box<dyn MidiBackend>, MidiError> {
+    Ok(Box::new(crate::linux::AlsaBackend::new(client_name)?))
+}
+
+#[cfg(target_os = "windows")]
+fn native_backend(client_name: &str) -> Result<Box<dyn MidiBackend>, MidiError> {
+    Ok(Box::new(crate::windows::WinMmBackend::new(client_name)?))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+fn native_backend(_client_name: &str) -> Result<Box<dyn MidiBackend>, MidiError> {
+    Err(MidiError::Unsupported("native backend"))
+}
@@ -0,0 +1,232 @@
+//! Delivery of incoming data through a channel, rather than a callback.
+//!
+//! Sources connected by [`ArcMutexMidiCon::connect_source_channel`] and its
+//! variants deliver [`MidiEvent`]s to a [`Receiver`], to be polled from the
+//! consumer's own thread, e.g., once per UI frame. Depending on the
+//! [`Delivery`] set by [`ArcMutexMidiCon::set_delivery`], events are passed
+//! through an unbounded `std::sync::mpsc` channel, or a fixed size lock-free
+//! ring buffer, for consumers that must not block, e.g., an audio thread.
+//!
+//! The data of short messages is held inline by [`MidiData`], so passing them
+//! through the ring buffer neither allocates nor frees memory. Only SysEx
+//! messages and chunks of several messages are allocated as they arrive, and
+//! freed by the consumer.
+
+use crate::error::MidiError;
+use crate::midi_con::{ArcMutexMidiCon, EndpointId};
+
+use ringbuf::traits::{Consumer, Producer, Split};
+use ringbuf::{HeapCons, HeapRb};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+/// Incoming data of a source, with the time it was received.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MidiEvent {
+    /// Id the source was connected with
+    pub source: EndpointId,
+    pub timestamp: Duration,
+    /// Raw MIDI data, SysEx messages being reassembled
+    pub data: MidiData,
+}
+
+/// Raw MIDI data of a [`MidiEvent`], dereferencing to a byte slice.
+///
+/// Up to 3 bytes, i.e., a short message, are held inline without allocating.
+#[derive(Clone)]
+pub struct MidiData(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; 3] },
+    Heap(Vec<u8>),
+}
+
+impl Deref for MidiData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(data) => data,
+        }
+    }
+}
+
+impl From<&[u8]> for MidiData {
+    fn from(data: &[u8]) -> Self {
+        if data.len() <= 3 {
+            let mut bytes = [0; 3];
+            bytes[..data.len()].copy_from_slice(data);
+            MidiData(Repr::Inline {
+                len: data.len() as u8,
+                bytes,
+            })
+        } else {
+            MidiData(Repr::Heap(data.to_vec()))
+        }
+    }
+}
+
+impl From<Vec<u8>> for MidiData {
+    fn from(data: Vec<u8>) -> Self {
+        if data.len() <= 3 {
+            MidiData::from(data.as_slice())
+        } else {
+            MidiData(Repr::Heap(data))
+        }
+    }
+}
+
+impl fmt::Debug for MidiData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl PartialEq for MidiData {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for MidiData {}
+
+impl PartialEq<[u8]> for MidiData {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for MidiData {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == **other
+    }
+}
+
+/// How incoming events are passed to a [`Receiver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Delivery {
+    /// Unbounded channel, events are never dropped.
+    #[default]
+    Channel,
+    /// Lock-free ring buffer holding up to `capacity` events, events arriving
+    /// while it is full are dropped, see [`Receiver::dropped`].
+    RingBuffer { capacity: usize },
+}
+
+/// Receiving end of a source connected through a channel.
+///
+/// The source stays connected when the receiver is dropped, until
+/// disconnected by [`ArcMutexMidiCon::disconnect_source`].
+pub struct Receiver {
+    inner: Inner,
+}
+
+enum Inner {
+    Channel(mpsc::Receiver<MidiEvent>),
+    RingBuffer {
+        consumer: HeapCons<MidiEvent>,
+        dropped: Arc<AtomicUsize>,
+    },
+}
+
+impl Receiver {
+    /// Take the next pending event, without blocking.
+    pub fn try_recv(&mut self) -> Option<MidiEvent> {
+        match &mut self.inner {
+            Inner::Channel(receiver) => receiver.try_recv().ok(),
+            Inner::RingBuffer { consumer, .. } => consumer.try_pop(),
+        }
+    }
+
+    /// Iterate over the pending events, without blocking.
+    pub fn try_iter(&mut self) -> impl Iterator<Item = MidiEvent> + '_ {
+        std::iter::from_fn(|| self.try_recv())
+    }
+
+    /// Number of events dropped so far as the ring buffer was full, always 0
+    /// for a channel.
+    pub fn dropped(&self) -> usize {
+        match &self.inner {
+            Inner::Channel(_) => 0,
+            Inner::RingBuffer { dropped, .. } => dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl ArcMutexMidiCon {
+    /// Set how events of sources connected through a channel afterwards are
+    /// delivered.
+    pub fn set_delivery(&self, delivery: Delivery) {
+        self.0.lock().unwrap().delivery = delivery;
+    }
+
+    /// Connect to a MIDI source by its id, delivering incoming data to the
+    /// returned receiver
+    pub fn connect_source_channel(&self, source: EndpointId) -> Result<Receiver, MidiError> {
+        let delivery = self.0.lock().unwrap().delivery;
+        match delivery {
+            Delivery::Channel => {
+                let (sender, receiver) = mpsc::channel();
+                self.connect_source(source, move |timestamp, data, _| {
+                    // the receiver may be gone, the source stays connected
+                    let _ = sender.send(MidiEvent {
+                        source,
+                        timestamp,
+                        data: data.into(),
+                    });
+                })?;
+                Ok(Receiver {
+                    inner: Inner::Channel(receiver),
+                })
+            }
+            Delivery::RingBuffer { capacity } => {
+                let (producer, consumer) = HeapRb::new(capacity.max(1)).split();
+                // the callback of a source is never invoked concurrently, so
+                // borrowing the producer never fails
+                let producer = RefCell::new(producer);
+                let dropped = Arc::new(AtomicUsize::new(0));
+                let producer_dropped = dropped.clone();
+                self.connect_source(source, move |timestamp, data, _| {
+                    let event = MidiEvent {
+                        source,
+                        timestamp,
+                        data: data.into(),
+                    };
+                    if producer.borrow_mut().try_push(event).is_err() {
+                        producer_dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                })?;
+                Ok(Receiver {
+                    inner: Inner::RingBuffer { consumer, dropped },
+                })
+            }
+        }
+    }
+
+    /// Connect to a MIDI source by its index, delivering incoming data to the
+    /// returned receiver
+    pub fn connect_source_by_index_channel(
+        &self,
+        source_index: usize,
+    ) -> Result<(EndpointId, Receiver), MidiError> {
+        let source = self.source_by_index(source_index)?;
+        Ok((source, self.connect_source_channel(source)?))
+    }
+
+    /// Connect to a MIDI source by its name, delivering incoming data to the
+    /// returned receiver
+    pub fn connect_source_by_name_channel(
+        &self,
+        source_name: &str,
+    ) -> Result<(EndpointId, Receiver), MidiError> {
+        let source = self.source_by_name(source_name)?;
+        Ok((source, self.connect_source_channel(source)?))
+    }
+}
@@ -0,0 +1,604 @@
+//! MIDI clock synchronization.
+//!
+//! A [`ClockMaster`] sends MIDI clock, 24 ticks per quarter note, with Start,
+//! Stop, Continue and Song Position Pointer to connected destinations. The
+//! timestamp of each tick is computed from the tempo rather than accumulated,
+//! and ticks are scheduled within a lookahead window by
+//! [`ArcMutexMidiCon::send_at`], so they are delivered without the jitter of
+//! [`ClockMaster::update`] being called.
+//!
+//! A [`ClockFollower`] tracks the transport and tempo of MIDI clock received
+//! from a source connected by [`ArcMutexMidiCon::connect_source_clock_follower`]
+//! and its variants, reporting changes as [`ClockEvent`].
+//!
+//! ```no_run
+//! use rmidi::clock::ClockMaster;
+//! use rmidi::midi_con::ArcMutexMidiCon;
+//!
+//! let midi_con = ArcMutexMidiCon::new()?;
+//! let destination = midi_con.connect_destination_by_index(0)?;
+//! let mut clock = ClockMaster::new(&midi_con, &[destination], 120.0)?;
+//! clock.start()?;
+//! while clock.update()? {
+//!     std::thread::sleep(std::time::Duration::from_millis(20));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::MidiError;
+use crate::message::{MidiMessage, MidiParser};
+use crate::midi_con::{ArcMutexMidiCon, EndpointId};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Interval of the dropout checks of a connected [`ClockFollower`].
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Clock ticks per quarter note
+pub const TICKS_PER_QUARTER: u64 = 24;
+
+/// Clock ticks per MIDI beat, i.e., sixteenth note, the unit of Song
+/// Position Pointer
+const TICKS_PER_SIXTEENTH: u64 = 6;
+
+/// Clock ticks per eighth note, the span of swing
+const TICKS_PER_EIGHTH: u64 = 2 * TICKS_PER_SIXTEENTH;
+
+/// MIDI clock master, see the [module](self) documentation.
+///
+/// Ticks are only sent while running. Changes of tempo and swing, and the
+/// transport messages, take effect at the first tick not scheduled yet, i.e.,
+/// within the lookahead.
+pub struct ClockMaster {
+    midi_con: ArcMutexMidiCon,
+    destinations: Vec<EndpointId>,
+    /// Beats per minute
+    tempo: f64,
+    /// Percentage of an eighth note taken by its first sixteenth
+    swing: f64,
+    lookahead: Duration,
+    /// Song position in ticks while stopped
+    position: u64,
+    opt_anchor: Option<Anchor>,
+    /// Next tick to schedule while running
+    next: u64,
+    /// Timestamp of the last Stop, transport messages following it
+    stop_timestamp: Duration,
+}
+
+/// Timestamp of a tick.
+#[derive(Clone, Copy)]
+struct Anchor {
+    timestamp: Duration,
+    tick: u64,
+}
+
+impl ClockMaster {
+    /// Clock master sending to the connected `destinations` at `tempo` beats
+    /// per minute, stopped at the start of the song.
+    pub fn new(
+        midi_con: &ArcMutexMidiCon,
+        destinations: &[EndpointId],
+        tempo: f64,
+    ) -> Result<Self, MidiError> {
+        let mut clock = ClockMaster {
+            midi_con: midi_con.clone(),
+            destinations: destinations.to_vec(),
+            tempo: 120.0,
+            swing: 50.0,
+            lookahead: Duration::from_millis(100),
+            position: 0,
+            opt_anchor: None,
+            next: 0,
+            stop_timestamp: Duration::ZERO,
+        };
+        clock.set_tempo(tempo)?;
+        Ok(clock)
+    }
+
+    pub fn destinations(&self) -> &[EndpointId] {
+        &self.destinations
+    }
+
+    /// Send to the connected `destination` as well, from the next tick on.
+    pub fn add_destination(&mut self, destination: EndpointId) {
+        if !self.destinations.contains(&destination) {
+            self.destinations.push(destination);
+        }
+    }
+
+    /// Stop sending to `destination`, leaving the ticks already scheduled.
+    pub fn remove_destination(&mut self, destination: EndpointId) {
+        self.destinations.retain(|id| *id != destination);
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Set the tempo in beats per minute, from 1 to 1000.
+    pub fn set_tempo(&mut self, tempo: f64) -> Result<(), MidiError> {
+        if !(1.0..=1000.0).contains(&tempo) {
+            return Err(MidiError::InvalidSetting {
+                field: "tempo",
+                value: tempo.to_string(),
+            });
+        }
+        self.reanchor();
+        self.tempo = tempo;
+        Ok(())
+    }
+
+    pub fn swing(&self) -> f64 {
+        self.swing
+    }
+
+    /// Set the swing as the percentage of an eighth note taken by its first
+    /// sixteenth, from 50 (straight) to 75 (dotted sixteenth and thirty
+    /// second).
+    pub fn set_swing(&mut self, swing: f64) -> Result<(), MidiError> {
+        if !(50.0..=75.0).contains(&swing) {
+            return Err(MidiError::InvalidSetting {
+                field: "swing",
+                value: swing.to_string(),
+            });
+        }
+        self.reanchor();
+        self.swing = swing;
+        Ok(())
+    }
+
+    /// Set how far ahead of time ticks are scheduled, 100 ms by default.
+    /// [`update`](Self::update) must be called at least as often.
+    pub fn set_lookahead(&mut self, lookahead: Duration) {
+        self.lookahead = lookahead;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.opt_anchor.is_some()
+    }
+
+    /// Song position in ticks, i.e., the ticks sent since the start of the
+    /// song, or due by now while running.
+    pub fn position(&self) -> u64 {
+        match self.opt_anchor {
+            Some(anchor) => self.tick_at(anchor, self.midi_con.now()),
+            None => self.position,
+        }
+    }
+
+    /// Send Start and run from the start of the song.
+    pub fn start(&mut self) -> Result<(), MidiError> {
+        let timestamp = self.transport_timestamp();
+        self.send_all(timestamp, &[0xfa])?;
+        self.run(timestamp, 0)
+    }
+
+    /// Send Stop, keeping the song position.
+    pub fn stop(&mut self) -> Result<(), MidiError> {
+        if self.opt_anchor.is_some() {
+            let timestamp = self.transport_timestamp();
+            self.send_all(timestamp, &[0xfc])?;
+            self.stop_timestamp = timestamp;
+            self.position = self.next;
+            self.opt_anchor = None;
+        }
+        Ok(())
+    }
+
+    /// Send Continue and run from the song position.
+    pub fn resume(&mut self) -> Result<(), MidiError> {
+        if self.opt_anchor.is_none() {
+            let timestamp = self.transport_timestamp();
+            self.send_all(timestamp, &[0xfb])?;
+            self.run(timestamp, self.position)?;
+        }
+        Ok(())
+    }
+
+    /// Move to the song position of `sixteenths`, sending Song Position
+    /// Pointer. While running, it is sent between Stop and Continue, as
+    /// receivers ignore it otherwise.
+    pub fn locate(&mut self, sixteenths: u16) -> Result<(), MidiError> {
+        if sixteenths > 0x3fff {
+            return Err(MidiError::InvalidMessage {
+                field: "song position",
+                value: sixteenths as u32,
+            });
+        }
+        let pointer = [0xf2, (sixteenths & 0x7f) as u8, (sixteenths >> 7) as u8];
+        let position = sixteenths as u64 * TICKS_PER_SIXTEENTH;
+        if self.opt_anchor.is_some() {
+            let timestamp = self.transport_timestamp();
+            for data in [&[0xfc][..], &pointer, &[0xfb]] {
+                self.send_all(timestamp, data)?;
+            }
+            self.run(timestamp, position)
+        } else {
+            self.send_all(self.transport_timestamp(), &pointer)?;
+            self.position = position;
+            Ok(())
+        }
+    }
+
+    /// Schedule the ticks due within the lookahead window, returning whether
+    /// running.
+    pub fn update(&mut self) -> Result<bool, MidiError> {
+        if let Some(anchor) = self.opt_anchor {
+            let horizon = self.midi_con.now() + self.lookahead;
+            loop {
+                let timestamp = self.timestamp(anchor, self.next);
+                if timestamp > horizon {
+                    break;
+                }
+                self.send_all(timestamp, &[0xf8])?;
+                self.next += 1;
+            }
+        }
+        Ok(self.opt_anchor.is_some())
+    }
+
+    /// Run from the tick at `position`, due at `timestamp`.
+    fn run(&mut self, timestamp: Duration, position: u64) -> Result<(), MidiError> {
+        self.opt_anchor = Some(Anchor {
+            timestamp,
+            tick: position,
+        });
+        self.next = position;
+        self.update()?;
+        Ok(())
+    }
+
+    /// Anchor at the next tick, before a change of timing.
+    fn reanchor(&mut self) {
+        if let Some(anchor) = self.opt_anchor {
+            self.opt_anchor = Some(Anchor {
+                timestamp: self.timestamp(anchor, self.next),
+                tick: self.next,
+            });
+        }
+    }
+
+    /// Timestamp of transport messages, at the next tick while running, and
+    /// not before the last Stop otherwise.
+    fn transport_timestamp(&self) -> Duration {
+        match self.opt_anchor {
+            Some(anchor) => self.timestamp(anchor, self.next),
+            None => self.midi_con.now().max(self.stop_timestamp),
+        }
+    }
+
+    fn send_all(&self, timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        for destination in &self.destinations {
+            self.midi_con.send_at(*destination, timestamp, data)?;
+        }
+        Ok(())
+    }
+
+    /// Duration of an eighth note in nanoseconds.
+    fn eighth(&self) -> f64 {
+        30e9 / self.tempo
+    }
+
+    /// Nanoseconds from the start of the song to `tick`, at the current tempo
+    /// and swing.
+    fn swung(&self, tick: u64) -> f64 {
+        let eighth = self.eighth();
+        let first = eighth * self.swing / 100.0;
+        let (eighths, tick) = (tick / TICKS_PER_EIGHTH, tick % TICKS_PER_EIGHTH);
+        let offset = if tick <= TICKS_PER_SIXTEENTH {
+            first * tick as f64 / TICKS_PER_SIXTEENTH as f64
+        } else {
+            let tick = tick - TICKS_PER_SIXTEENTH;
+            first + (eighth - first) * tick as f64 / TICKS_PER_SIXTEENTH as f64
+        };
+        eighths as f64 * eighth + offset
+    }
+
+    /// Timestamp of `tick`.
+    fn timestamp(&self, anchor: Anchor, tick: u64) -> Duration {
+        let nanos = self.swung(tick) - self.swung(anchor.tick);
+        anchor.timestamp + Duration::from_nanos(nanos.round() as u64)
+    }
+
+    /// Last tick due at `timestamp`.
+    fn tick_at(&self, anchor: Anchor, timestamp: Duration) -> u64 {
+        // the anchor is ahead of time after a change of timing, following the
+        // ticks scheduled before
+        if timestamp < anchor.timestamp {
+            return anchor.tick.saturating_sub(1);
+        }
+        let elapsed = (timestamp - anchor.timestamp).as_nanos() as f64;
+        let swung = self.swung(anchor.tick) + elapsed;
+        let eighth = self.eighth();
+        let first = eighth * self.swing / 100.0;
+        let eighths = (swung / eighth).floor();
+        let offset = swung - eighths * eighth;
+        let ticks = if offset < first {
+            offset / first * TICKS_PER_SIXTEENTH as f64
+        } else {
+            (1.0 + (offset - first) / (eighth - first)) * TICKS_PER_SIXTEENTH as f64
+        };
+        // not behind a tick due by rounding
+        eighths as u64 * TICKS_PER_EIGHTH + (ticks + 1e-6).floor() as u64
+    }
+}
+
+/// Change of the received clock, reported by a [`ClockFollower`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClockEvent {
+    Start,
+    Stop,
+    Continue,
+    /// Song Position Pointer, in sixteenth notes
+    SongPosition(u16),
+    /// Estimated tempo in beats per minute, once known and on changes of at
+    /// least the tempo threshold
+    Tempo(f64),
+    /// No clock received within the timeout, the tempo being unknown until
+    /// it resumes
+    Dropout,
+}
+
+/// Follower of the MIDI clock of a source.
+///
+/// The tempo is estimated from the timestamps of the ticks of the last
+/// quarter note, averaging out their jitter. Dropouts are reported when the
+/// next tick arrives late, or by [`check`](Self::check) in the meantime, which
+/// a watchdog thread calls every 50 ms once the follower is connected by
+/// [`ArcMutexMidiCon::connect_source_clock_follower`] or its variants, so a
+/// clock that just stops is reported as well. The transport state is kept on
+/// dropouts, as the clock may resume.
+///
+/// Clones share the same state and callback.
+#[derive(Clone)]
+pub struct ClockFollower {
+    state: Arc<Mutex<FollowerState>>,
+    callback: Arc<dyn Fn(&ClockEvent) + Send + Sync>,
+}
+
+struct FollowerState {
+    parser: MidiParser,
+    running: bool,
+    /// Whether the next tick is the first after Start or Continue, played at
+    /// the song position rather than advancing it
+    first: bool,
+    /// Song position in ticks
+    position: u64,
+    /// Timestamps of the ticks of the last quarter note
+    ticks: VecDeque<Duration>,
+    /// Tempo last reported
+    opt_tempo: Option<f64>,
+    timeout: Duration,
+    threshold: f64,
+    /// Whether the dropout since the last tick was reported
+    dropped: bool,
+    /// Whether a watchdog thread checks for dropouts
+    watched: bool,
+}
+
+impl ClockFollower {
+    /// Follower calling `callback` on changes, stopped at the start of the
+    /// song. Callbacks are never invoked under the lock of the follower, so
+    /// they may call its methods.
+    pub fn new(callback: impl Fn(&ClockEvent) + Send + Sync + 'static) -> Self {
+        ClockFollower {
+            state: Arc::new(Mutex::new(FollowerState {
+                parser: MidiParser::new(),
+                running: false,
+                first: false,
+                position: 0,
+                ticks: VecDeque::new(),
+                opt_tempo: None,
+                timeout: Duration::from_millis(250),
+                threshold: 1.0,
+                dropped: false,
+                watched: false,
+            })),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Set the time without ticks reported as dropout, 250 ms by default.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.state.lock().unwrap().timeout = timeout;
+    }
+
+    /// Set the change of the estimated tempo reported, 1 beat per minute by
+    /// default.
+    pub fn set_tempo_threshold(&self, threshold: f64) {
+        self.state.lock().unwrap().threshold = threshold;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.lock().unwrap().running
+    }
+
+    /// Song position in quarter notes.
+    pub fn position(&self) -> f64 {
+        self.state.lock().unwrap().position as f64 / TICKS_PER_QUARTER as f64
+    }
+
+    /// Estimated tempo in beats per minute, if known.
+    pub fn tempo(&self) -> Option<f64> {
+        self.state.lock().unwrap().estimate()
+    }
+
+    /// Follow incoming data received at `timestamp`.
+    pub fn receive(&self, timestamp: Duration, data: &[u8]) {
+        let mut events = vec![];
+        {
+            let state = &mut *self.state.lock().unwrap();
+            let mut messages = vec![];
+            state.parser.parse(data, |message| messages.push(message));
+            for message in messages {
+                state.handle(timestamp, message, &mut events);
+            }
+        }
+        for event in &events {
+            (self.callback)(event);
+        }
+    }
+
+    /// Report a dropout if no tick was received within the timeout before
+    /// `now`, e.g., [`ArcMutexMidiCon::now`].
+    pub fn check(&self, now: Duration) {
+        let mut events = vec![];
+        {
+            let state = &mut *self.state.lock().unwrap();
+            if let Some(last) = state.ticks.back()
+                && now.saturating_sub(*last) > state.timeout
+            {
+                state.dropout(&mut events);
+            }
+        }
+        for event in &events {
+            (self.callback)(event);
+        }
+    }
+
+    /// Start the watchdog thread checking for dropouts at the time of
+    /// `midi_con`, unless running already, until either is gone.
+    fn watch(&self, midi_con: &ArcMutexMidiCon) {
+        if std::mem::replace(&mut self.state.lock().unwrap().watched, true) {
+            return;
+        }
+        let state = Arc::downgrade(&self.state);
+        let callback = self.callback.clone();
+        let midi_con = Arc::downgrade(&midi_con.0);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(WATCHDOG_INTERVAL);
+                let (Some(state), Some(midi_con)) = (state.upgrade(), midi_con.upgrade()) else {
+                    return;
+                };
+                let now = ArcMutexMidiCon(midi_con).now();
+                let follower = ClockFollower {
+                    state,
+                    callback: callback.clone(),
+                };
+                follower.check(now);
+            }
+        });
+    }
+}
+
+impl FollowerState {
+    fn handle(&mut self, timestamp: Duration, message: MidiMessage, events: &mut Vec<ClockEvent>) {
+        match message {
+            MidiMessage::TimingClock => {
+                if let Some(last) = self.ticks.back()
+                    && timestamp.saturating_sub(*last) > self.timeout
+                {
+                    self.dropout(events);
+                }
+                self.dropped = false;
+                if self.ticks.len() > TICKS_PER_QUARTER as usize {
+                    self.ticks.pop_front();
+                }
+                self.ticks.push_back(timestamp);
+                if self.running {
+                    if self.first {
+                        self.first = false;
+                    } else {
+                        self.position += 1;
+                    }
+                }
+                if let Some(tempo) = self.estimate()
+                    && self
+                        .opt_tempo
+                        .is_none_or(|reported| (tempo - reported).abs() >= self.threshold)
+                {
+                    self.opt_tempo = Some(tempo);
+                    events.push(ClockEvent::Tempo(tempo));
+                }
+            }
+            MidiMessage::Start => {
+                self.running = true;
+                self.first = true;
+                self.position = 0;
+                events.push(ClockEvent::Start);
+            }
+            MidiMessage::Continue => {
+                self.running = true;
+                self.first = true;
+                events.push(ClockEvent::Continue);
+            }
+            MidiMessage::Stop => {
+                self.running = false;
+                events.push(ClockEvent::Stop);
+            }
+            MidiMessage::SongPosition(sixteenths) => {
+                self.position = sixteenths as u64 * TICKS_PER_SIXTEENTH;
+                events.push(ClockEvent::SongPosition(sixteenths));
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget about the tempo, reporting the dropout once.
+    fn dropout(&mut self, events: &mut Vec<ClockEvent>) {
+        self.ticks.clear();
+        self.opt_tempo = None;
+        if !self.dropped {
+            self.dropped = true;
+            events.push(ClockEvent::Dropout);
+        }
+    }
+
+    /// Tempo by the average tick over the ticks received, once spanning a
+    /// sixteenth note.
+    fn estimate(&self) -> Option<f64> {
+        let intervals = self.ticks.len().checked_sub(1)?;
+        if intervals < TICKS_PER_SIXTEENTH as usize {
+            return None;
+        }
+        let span = (*self.ticks.back()? - *self.ticks.front()?).as_nanos() as f64;
+        let tick = span / intervals as f64;
+        Some(60e9 / (tick * TICKS_PER_QUARTER as f64))
+    }
+}
+
+impl ArcMutexMidiCon {
+    /// Connect to a MIDI source by its id, following its clock with
+    /// `follower`
+    pub fn connect_source_clock_follower(
+        &self,
+        source: EndpointId,
+        follower: &ClockFollower,
+    ) -> Result<(), MidiError> {
+        let receiver = follower.clone();
+        self.connect_source(source, move |timestamp, data, _| {
+            receiver.receive(timestamp, data)
+        })?;
+        follower.watch(self);
+        Ok(())
+    }
+
+    /// Connect to a MIDI source by its index, following its clock with
+    /// `follower`
+    pub fn connect_source_by_index_clock_follower(
+        &self,
+        source_index: usize,
+        follower: &ClockFollower,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_index(source_index)?;
+        self.connect_source_clock_follower(source, follower)?;
+        Ok(source)
+    }
+
+    /// Connect to a MIDI source by its name, following its clock with
+    /// `follower`
+    pub fn connect_source_by_name_clock_follower(
+        &self,
+        source_name: &str,
+        follower: &ClockFollower,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_name(source_name)?;
+        self.connect_source_clock_follower(source, follower)?;
+        Ok(source)
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+use crate::midi_con::EndpointId;
+
+/// Errors returned by the MIDI API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiError {
+    /// No source or destination at the index.
+    UnknownIndex(usize),
+    /// No source or destination with the name.
+    UnknownName(String),
+    /// No source or destination with the id.
+    UnknownEndpoint(EndpointId),
+    /// The source or destination is already connected.
+    AlreadyConnected(EndpointId),
+    /// The source or destination is not connected.
+    NotConnected(EndpointId),
+    /// A call to the platform MIDI API failed with the given status (OSStatus
+    /// on macOS, errno on Linux, MMRESULT on Windows).
+    Backend { call: &'static str, status: i32 },
+    /// The device of the source or destination has been detached.
+    DeviceGone,
+    /// A field of a MIDI message is out of range, e.g., a 7 bit data byte
+    /// above 127, or a channel outside of 1 to 16.
+    InvalidMessage { field: &'static str, value: u32 },
+    /// An incoming SysEx message was interrupted by another status byte.
+    SysExInterrupted,
+    /// An incoming SysEx message exceeded the maximum size.
+    SysExTooLong(usize),
+    /// The next piece of an incoming SysEx message did not arrive in time.
+    SysExTimeout,
+    /// The feature is not supported by the backend, e.g., virtual endpoints
+    /// on WinMM.
+    Unsupported(&'static str),
+    /// A Standard MIDI File is malformed, `offset` being the position of the
+    /// offending byte in the file.
+    InvalidFile { offset: usize, reason: &'static str },
+    /// A setting is out of range, e.g., a tempo of 0 beats per minute,
+    /// `value` being the rejected value as formatted.
+    InvalidSetting { field: &'static str, value: String },
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::UnknownIndex(index) => write!(f, "no endpoint at index {}", index),
+            MidiError::UnknownName(name) => write!(f, "no endpoint named {:?}", name),
+            MidiError::UnknownEndpoint(id) => write!(f, "no endpoint with id {:?}", id),
+            MidiError::AlreadyConnected(id) => write!(f, "endpoint {:?} already connected", id),
+            MidiError::NotConnected(id) => write!(f, "endpoint {:?} not connected", id),
+            MidiError::Backend { call, status } => write!(f, "{} failed: {}", call, status),
+            MidiError::DeviceGone => write!(f, "device detached"),
+            MidiError::InvalidMessage { field, value } => {
+                write!(f, "{} out of range: {}", field, value)
+            }
+            MidiError::SysExInterrupted => write!(f, "SysEx interrupted"),
+            MidiError::SysExTooLong(max_size) => {
+                write!(f, "SysEx longer than {} bytes", max_size)
+            }
+            MidiError::SysExTimeout => write!(f, "SysEx timed out"),
+            MidiError::Unsupported(feature) => write!(f, "{} not supported", feature),
+            MidiError::InvalidFile { offset, reason } => {
+                write!(f, "invalid MIDI file at byte {}: {}", offset, reason)
+            }
+            MidiError::InvalidSetting { field, value } => {
+                write!(f, "{} out of range: {}", field, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
@@ -1,9 +1,26 @@
+pub mod backend;
+pub mod builder;
+pub mod channel;
+pub mod clock;
+pub mod error;
+pub mod message;
+pub mod midi_con;
+pub mod mock;
+pub mod player;
+pub mod recorder;
+mod scheduler;
+pub mod session;
+pub mod smf;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod sysex;
+pub mod virtual_endpoint;
+
 #[cfg(target_os = "macos")]
-mod macos;
-#[cfg(target_os = "macos")]
-pub use macos::*;
+pub mod macos;
+
+#[cfg(target_os = "linux")]
+pub mod linux;
 
 #[cfg(target_os = "windows")]
-mod windows;
-#[cfg(target_os = "windows")]
-pub use windows::*;
+pub mod windows;
@@ -0,0 +1,567 @@
+use alsa::seq::{
+    Addr, ClientIter, Event, EventType, MidiEvent, PortCap, PortInfo, PortIter, PortSubscribe,
+    PortType, Remove, RemoveEvents, Seq,
+};
+use alsa::{Direction, PollDescriptors};
+use log::{trace, warn};
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    HandlerReceiver, HandlerSender, InputCallback, InputConnection, MidiBackend,
+    NotificationHandler, OutputConnection, notification_channel,
+};
+use crate::error::MidiError;
+use crate::midi_con::{EndpointId, EndpointInfo, Notification};
+use crate::sysex::{Piece, SysExSplitter};
+
+/// Size of the buffer used to decode incoming sequencer events to raw MIDI.
+const DECODE_BUFFER_SIZE: usize = 1024;
+
+/// Poll timeout of the input thread, bounding the time to shut it down.
+const POLL_TIMEOUT_MS: i32 = 100;
+
+/// Linux errno values reported for a client or port that went away.
+const ENOENT: i32 = 2;
+const ENXIO: i32 = 6;
+
+/// MIDI backend on top of the ALSA sequencer.
+///
+/// Sources are the readable and destinations the writable sequencer ports of
+/// other clients, e.g. hardware ports, `snd-seq-dummy` or virtual client ports.
+/// Input is received by a dedicated thread, which also listens to the system
+/// announce port for clients and ports coming and going. Incoming events are
+/// timestamped by the sequencer, in real time of a queue started along with
+/// the backend, and scheduled output is delivered by another queue started at
+/// the same time.
+pub struct AlsaBackend {
+    /// Sequencer handle used for enumeration and output
+    seq: Arc<Mutex<Seq>>,
+    /// Sequencer handle owning the input ports, polled by the input thread
+    input_seq: Arc<Mutex<Seq>>,
+    /// Client ids of `seq` and `input_seq`
+    own_clients: [i32; 2],
+    /// Queue timestamping incoming events, owned by `input_seq`
+    queue: i32,
+    /// Queue scheduling output, owned by `seq`
+    output_queue: i32,
+    /// Start of the queues, the session epoch
+    epoch: Instant,
+    callbacks: Arc<Mutex<HashMap<i32, Arc<Mutex<InputCallback>>>>>,
+    handler_tx: HandlerSender,
+    running: Arc<AtomicBool>,
+}
+
+impl AlsaBackend {
+    pub fn new(client_name: &str) -> Result<Self, MidiError> {
+        // the name is only informative, so drop any interior nul bytes
+        let client_name = CString::new(client_name.replace('\0', "")).unwrap();
+
+        let seq = Seq::open(None, Some(Direction::Playback), false)?;
+        seq.set_client_name(&client_name)?;
+
+        let input_seq = Seq::open(None, None, true)?;
+        input_seq.set_client_name(&client_name)?;
+
+        // listen to clients and ports coming and going
+        let mut announce = PortInfo::empty()?;
+        announce.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE | PortCap::NO_EXPORT);
+        announce.set_type(PortType::APPLICATION);
+        announce.set_name(c"announce");
+        input_seq.create_port(&announce)?;
+        let subscribe = PortSubscribe::empty()?;
+        subscribe.set_sender(Addr::system_announce());
+        subscribe.set_dest(Addr {
+            client: input_seq.client_id()?,
+            port: announce.get_port(),
+        });
+        input_seq.subscribe_port(&subscribe)?;
+
+        let queue = input_seq.alloc_named_queue(c"rmidi input")?;
+        let output_queue = seq.alloc_named_queue(c"rmidi output")?;
+        input_seq.control_queue(queue, EventType::Start, 0, None)?;
+        seq.control_queue(output_queue, EventType::Start, 0, None)?;
+        input_seq.drain_output()?;
+        seq.drain_output()?;
+        let epoch = Instant::now();
+
+        let own_clients = [seq.client_id()?, input_seq.client_id()?];
+        let input_seq = Arc::new(Mutex::new(input_seq));
+        let callbacks = Arc::new(Mutex::new(HashMap::new()));
+        let (handler_tx, handler_rx) = notification_channel();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_input_seq = input_seq.clone();
+        let thread_callbacks = callbacks.clone();
+        let thread_running = running.clone();
+        thread::spawn(move || {
+            if let Err(err) = input_thread(
+                thread_input_seq,
+                epoch,
+                thread_callbacks,
+                handler_rx,
+                thread_running,
+            ) {
+                warn!("Input thread failed: {}", err);
+            }
+        });
+
+        Ok(AlsaBackend {
+            seq: Arc::new(Mutex::new(seq)),
+            input_seq,
+            own_clients,
+            queue,
+            output_queue,
+            epoch,
+            callbacks,
+            handler_tx,
+            running,
+        })
+    }
+
+    /// Port of other clients having `caps`, with its address and name.
+    fn port(&self, caps: PortCap, id: EndpointId) -> Result<(Addr, String), MidiError> {
+        self.ports(caps)
+            .into_iter()
+            .find(|(addr, _)| endpoint_id(*addr) == id)
+            .map(|(addr, info)| (addr, info.name))
+            .ok_or(MidiError::UnknownEndpoint(id))
+    }
+
+    /// Addresses and properties of the ports of other clients having `caps`.
+    fn ports(&self, caps: PortCap) -> Vec<(Addr, EndpointInfo)> {
+        let seq = self.seq.lock().unwrap();
+        let mut ports = vec![];
+        for client in ClientIter::new(&seq) {
+            let client_id = client.get_client();
+            // skip the system client (timer and announce) and ourselves
+            if client_id == 0 || self.own_clients.contains(&client_id) {
+                continue;
+            }
+            for port in PortIter::new(&seq, client_id) {
+                if port.get_capability().contains(caps)
+                    && !port.get_capability().contains(PortCap::NO_EXPORT)
+                {
+                    let client_name = client.get_name().unwrap_or("Unknown");
+                    let port_name = port.get_name().unwrap_or("Unknown");
+                    let mut info = EndpointInfo::new(
+                        endpoint_id(port.addr()),
+                        format!("{}:{}", client_name, port_name),
+                    );
+                    info.display_name = port_name.to_string();
+                    // the card of kernel clients, i.e., hardware
+                    info.model = client
+                        .get_card()
+                        .ok()
+                        .and_then(|card| alsa::Card::new(card).get_name().ok());
+                    info.is_virtual = !port.get_type().contains(PortType::HARDWARE);
+                    info.device = Some(client_name.to_string());
+                    ports.push((port.addr(), info));
+                }
+            }
+        }
+        ports
+    }
+
+    /// Create a writable port on `input_seq`, delivering incoming data to
+    /// `callback`. The port is deleted again when the input is dropped.
+    fn create_input_port(
+        &self,
+        input_seq: &Seq,
+        name: &CStr,
+        callback: InputCallback,
+    ) -> Result<AlsaInput, MidiError> {
+        let mut port_info = PortInfo::empty()?;
+        port_info.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE);
+        port_info.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
+        port_info.set_name(name);
+        port_info.set_timestamping(true);
+        port_info.set_timestamp_real(true);
+        port_info.set_timestamp_queue(self.queue);
+        input_seq.create_port(&port_info)?;
+        let port = port_info.get_port();
+
+        // register the callback before data can arrive
+        self.callbacks
+            .lock()
+            .unwrap()
+            .insert(port, Arc::new(Mutex::new(callback)));
+        Ok(AlsaInput {
+            input_seq: self.input_seq.clone(),
+            callbacks: self.callbacks.clone(),
+            port,
+        })
+    }
+
+    /// Create a readable port on `seq`, sending to `destination`, or to the
+    /// subscribers of the port if `None`. The port is deleted again when the
+    /// output is dropped.
+    fn create_output_port(
+        &self,
+        seq: &Seq,
+        name: &CStr,
+        destination: Option<Addr>,
+    ) -> Result<AlsaOutput, MidiError> {
+        let encoder = Encoder(MidiEvent::new(0)?);
+        let mut port_info = PortInfo::empty()?;
+        port_info.set_capability(PortCap::READ | PortCap::SUBS_READ);
+        port_info.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
+        port_info.set_name(name);
+        seq.create_port(&port_info)?;
+        Ok(AlsaOutput {
+            seq: self.seq.clone(),
+            port: port_info.get_port(),
+            destination,
+            queue: self.output_queue,
+            encoder,
+            splitter: SysExSplitter::new(),
+        })
+    }
+}
+
+/// The id of a port is made up of its client and port numbers.
+fn endpoint_id(addr: Addr) -> EndpointId {
+    EndpointId(((addr.client as u64) << 32) | addr.port as u64)
+}
+
+impl Drop for AlsaBackend {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl MidiBackend for AlsaBackend {
+    fn sources(&self) -> Vec<(EndpointId, String)> {
+        self.source_info()
+            .into_iter()
+            .map(|info| (info.id, info.name))
+            .collect()
+    }
+
+    fn destinations(&self) -> Vec<(EndpointId, String)> {
+        self.destination_info()
+            .into_iter()
+            .map(|info| (info.id, info.name))
+            .collect()
+    }
+
+    fn source_info(&self) -> Vec<EndpointInfo> {
+        self.ports(PortCap::READ | PortCap::SUBS_READ)
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect()
+    }
+
+    fn destination_info(&self) -> Vec<EndpointInfo> {
+        self.ports(PortCap::WRITE | PortCap::SUBS_WRITE)
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect()
+    }
+
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn set_notification_handler(&mut self, handler: NotificationHandler) {
+        self.handler_tx.install(handler);
+    }
+
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError> {
+        let (source, name) = self.port(PortCap::READ | PortCap::SUBS_READ, source)?;
+
+        let subscribe = PortSubscribe::empty()?;
+        let input_seq = self.input_seq.lock().unwrap();
+        let input = self.create_input_port(&input_seq, c"input", callback)?;
+
+        subscribe.set_sender(source);
+        subscribe.set_dest(Addr {
+            client: self.own_clients[1],
+            port: input.port,
+        });
+        let result = input_seq.subscribe_port(&subscribe);
+        // on failure, the port is deleted again when the input is dropped
+        drop(input_seq);
+        result?;
+        trace!("Connected to source: {}", name);
+
+        Ok(Box::new(input))
+    }
+
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError> {
+        let (destination, name) = self.port(PortCap::WRITE | PortCap::SUBS_WRITE, destination)?;
+
+        let subscribe = PortSubscribe::empty()?;
+        let seq = self.seq.lock().unwrap();
+        let output = self.create_output_port(&seq, c"output", Some(destination))?;
+
+        subscribe.set_sender(Addr {
+            client: self.own_clients[0],
+            port: output.port,
+        });
+        subscribe.set_dest(destination);
+        let result = seq.subscribe_port(&subscribe);
+        // on failure, the port is deleted again when the output is dropped
+        drop(seq);
+        result?;
+        trace!("Connected to destination: {}", name);
+
+        Ok(Box::new(output))
+    }
+
+    /// Virtual sources are readable ports of the output sequencer, sending
+    /// to their subscribers.
+    fn create_virtual_source(
+        &mut self,
+        name: &str,
+    ) -> Result<(EndpointId, Box<dyn OutputConnection>), MidiError> {
+        let seq = self.seq.lock().unwrap();
+        let output = self.create_output_port(&seq, &port_name(name), None)?;
+        let id = endpoint_id(Addr {
+            client: self.own_clients[0],
+            port: output.port,
+        });
+        trace!("Created virtual source: {}", name);
+        Ok((id, Box::new(output)))
+    }
+
+    /// Virtual destinations are writable ports of the input sequencer, other
+    /// clients subscribe to.
+    fn create_virtual_destination(
+        &mut self,
+        name: &str,
+        callback: InputCallback,
+    ) -> Result<(EndpointId, Box<dyn InputConnection>), MidiError> {
+        let input_seq = self.input_seq.lock().unwrap();
+        let input = self.create_input_port(&input_seq, &port_name(name), callback)?;
+        let id = endpoint_id(Addr {
+            client: self.own_clients[1],
+            port: input.port,
+        });
+        trace!("Created virtual destination: {}", name);
+        Ok((id, Box::new(input)))
+    }
+}
+
+/// Port name, dropping any interior nul bytes as for the client name.
+fn port_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap()
+}
+
+/// Receive events on the input sequencer until the backend is dropped.
+fn input_thread(
+    input_seq: Arc<Mutex<Seq>>,
+    epoch: Instant,
+    callbacks: Arc<Mutex<HashMap<i32, Arc<Mutex<InputCallback>>>>>,
+    mut handler_rx: HandlerReceiver,
+    running: Arc<AtomicBool>,
+) -> Result<(), alsa::Error> {
+    let decoder = MidiEvent::new(DECODE_BUFFER_SIZE as u32)?;
+    decoder.enable_running_status(false);
+    let mut fds = {
+        let input_seq = input_seq.lock().unwrap();
+        (&*input_seq, Some(Direction::Capture)).get()?
+    };
+
+    while running.load(Ordering::Relaxed) {
+        if alsa::poll::poll(&mut fds, POLL_TIMEOUT_MS).unwrap_or(0) == 0 {
+            continue;
+        }
+
+        // drain pending events, but dispatch them without holding the sequencer
+        let mut received = vec![];
+        let mut setup_changed = false;
+        {
+            let input_seq = input_seq.lock().unwrap();
+            let mut input = input_seq.input();
+            while let Ok(mut event) = input.event_input() {
+                // stamped by the queue, which started at the epoch
+                let timestamp = event.get_time().unwrap_or_else(|| epoch.elapsed());
+                match event.get_type() {
+                    EventType::ClientStart
+                    | EventType::ClientExit
+                    | EventType::PortStart
+                    | EventType::PortExit
+                    | EventType::PortChange => setup_changed = true,
+                    EventType::Sysex => {
+                        if let Some(data) = event.get_ext() {
+                            received.push((event.get_dest().port, timestamp, data.to_vec()));
+                        }
+                    }
+                    _ => {
+                        let mut buffer = [0; DECODE_BUFFER_SIZE];
+                        if let Ok(len) = decoder.decode(&mut buffer, &mut event) {
+                            received.push((
+                                event.get_dest().port,
+                                timestamp,
+                                buffer[..len].to_vec(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (port, timestamp, data) in received {
+            let opt_callback = callbacks.lock().unwrap().get(&port).cloned();
+            if let Some(callback) = opt_callback {
+                (callback.lock().unwrap())(timestamp, &data);
+            }
+        }
+
+        if setup_changed {
+            handler_rx.notify(&Notification::SetupChanged);
+        }
+    }
+    Ok(())
+}
+
+struct AlsaInput {
+    input_seq: Arc<Mutex<Seq>>,
+    callbacks: Arc<Mutex<HashMap<i32, Arc<Mutex<InputCallback>>>>>,
+    port: i32,
+}
+
+impl InputConnection for AlsaInput {}
+
+impl Drop for AlsaInput {
+    fn drop(&mut self) {
+        // deleting the port also removes its subscription
+        if let Err(err) = self.input_seq.lock().unwrap().delete_port(self.port) {
+            warn!("Failed to delete input port {}: {}", self.port, err);
+        }
+        self.callbacks.lock().unwrap().remove(&self.port);
+    }
+}
+
+impl From<alsa::Error> for MidiError {
+    fn from(err: alsa::Error) -> Self {
+        match err.errno().abs() {
+            // the destination port or client went away
+            ENOENT | ENXIO => MidiError::DeviceGone,
+            status => MidiError::Backend {
+                call: err.func(),
+                status,
+            },
+        }
+    }
+}
+
+/// Encoder of raw MIDI into sequencer events.
+struct Encoder(MidiEvent);
+
+// SAFETY: `snd_midi_event_t` is a plain heap allocated parser state without
+// any thread affinity, it is only ever accessed through `&mut AlsaOutput`.
+unsafe impl Send for Encoder {}
+
+struct AlsaOutput {
+    seq: Arc<Mutex<Seq>>,
+    port: i32,
+    /// Destination sent to, `None` for the subscribers of a virtual source
+    destination: Option<Addr>,
+    /// Queue scheduling output, shared by all outputs
+    queue: i32,
+    encoder: Encoder,
+    splitter: SysExSplitter,
+}
+
+impl AlsaOutput {
+    /// Encode `data` into events, passing each to `f`.
+    ///
+    /// SysEx messages are split off by the splitter, so a message split over
+    /// several calls is sent in pieces rather than held back by the encoder.
+    fn encode(
+        &mut self,
+        data: &[u8],
+        mut f: impl FnMut(&Seq, Event) -> Result<(), MidiError>,
+    ) -> Result<(), MidiError> {
+        let seq = self.seq.lock().unwrap();
+        for piece in self.splitter.split(data) {
+            match piece {
+                Piece::SysEx(sysex) => {
+                    let mut event = Event::new_ext(EventType::Sysex, sysex);
+                    event.set_source(self.port);
+                    f(&seq, event)?;
+                }
+                Piece::Other(mut other) => {
+                    self.encoder.0.resize_buffer(other.len() as u32)?;
+                    while !other.is_empty() {
+                        let (consumed, opt_event) = self.encoder.0.encode(other)?;
+                        if let Some(mut event) = opt_event {
+                            event.set_source(self.port);
+                            f(&seq, event)?;
+                        }
+                        other = &other[consumed..];
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputConnection for AlsaOutput {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.encode(data, |seq, mut event| {
+            event.set_subs();
+            event.set_direct();
+            seq.event_output_direct(&mut event)?;
+            Ok(())
+        })
+    }
+
+    /// Scheduled events are removed by destination, so only connections to a
+    /// destination schedule natively.
+    fn schedules(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    fn send_at(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        let Some(destination) = self.destination else {
+            return self.send(data);
+        };
+        let queue = self.queue;
+        self.encode(data, |seq, mut event| {
+            // addressed to the destination rather than the subscribers, so
+            // pending events can be removed by destination
+            event.set_dest(destination);
+            event.schedule_real(queue, false, timestamp);
+            seq.event_output(&mut event)?;
+            Ok(())
+        })?;
+        self.seq.lock().unwrap().drain_output()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), MidiError> {
+        let Some(destination) = self.destination else {
+            return Ok(());
+        };
+        let remove = RemoveEvents::new()?;
+        remove.set_condition(Remove::OUTPUT | Remove::DEST);
+        remove.set_queue(self.queue);
+        remove.set_dest(destination);
+        self.seq.lock().unwrap().remove_events(remove)?;
+        Ok(())
+    }
+}
+
+impl Drop for AlsaOutput {
+    fn drop(&mut self) {
+        if let Err(err) = self.seq.lock().unwrap().delete_port(self.port) {
+            warn!("Failed to delete output port {}: {}", self.port, err);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+mod alsa_seq;
+
+pub use alsa_seq::*;
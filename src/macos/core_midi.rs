@@ -0,0 +1,435 @@
+use core_foundation::base::TCFType;
+use core_foundation::runloop::{CFRunLoop, CFRunLoopRunResult, kCFRunLoopDefaultMode};
+use core_foundation::string::{CFString, CFStringRef};
+use coremidi::{
+    Client, Destination, Destinations, InputPort, Object, OutputPort, PacketBuffer, Properties,
+    Source, Sources, VirtualDestination, VirtualSource,
+};
+use coremidi_sys::{
+    ItemCount, MIDIEndpointGetEntity, MIDIEndpointRef, MIDIEntityGetDevice, MIDIGetDestination,
+    MIDIGetSource, MIDIObjectGetStringProperty, MIDIObjectRef, kMIDIPropertyName,
+};
+use log::trace;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use crate::backend::{
+    HandlerSender, InputCallback, InputConnection, MidiBackend, NotificationHandler,
+    OutputConnection, notification_channel,
+};
+use crate::error::MidiError;
+use crate::midi_con::{EndpointId, EndpointInfo, Notification};
+
+/// OSStatus of CoreMIDI calls on endpoints that no longer exist.
+const K_MIDI_OBJECT_NOT_FOUND: i32 = -10832;
+
+#[repr(C)]
+struct MachTimebaseInfo {
+    numer: u32,
+    denom: u32,
+}
+
+unsafe extern "C" {
+    fn mach_absolute_time() -> u64;
+    fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+}
+
+/// Conversion of host time, the timebase of CoreMIDI timestamps, to the time
+/// since the session epoch.
+#[derive(Clone, Copy)]
+struct HostClock {
+    /// Host time of the session epoch
+    epoch: u64,
+    numer: u32,
+    denom: u32,
+}
+
+impl HostClock {
+    fn new() -> Self {
+        let mut info = MachTimebaseInfo { numer: 1, denom: 1 };
+        unsafe { mach_timebase_info(&mut info) };
+        HostClock {
+            epoch: unsafe { mach_absolute_time() },
+            numer: info.numer,
+            denom: info.denom,
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.since_epoch(unsafe { mach_absolute_time() })
+    }
+
+    /// Host time of `timestamp` since the epoch.
+    fn host_time(&self, timestamp: Duration) -> u64 {
+        let ticks = timestamp.as_nanos() * self.denom as u128 / self.numer as u128;
+        self.epoch + ticks as u64
+    }
+
+    /// Time since the epoch of `host_time`, 0 meaning now.
+    fn since_epoch(&self, host_time: u64) -> Duration {
+        if host_time == 0 {
+            return self.now();
+        }
+        let ticks = host_time.saturating_sub(self.epoch) as u128;
+        Duration::from_nanos((ticks * self.numer as u128 / self.denom as u128) as u64)
+    }
+}
+
+/// MIDI backend on top of Apple CoreMIDI.
+///
+/// CoreMIDI delivers notifications on the run loop of the thread that created
+/// the client, so the client is created on a dedicated thread running its own
+/// run loop. Applications thus don't need to run a `CFRunLoop` themselves.
+pub struct CoreMidiBackend {
+    client: Client,
+    clock: HostClock,
+    handler_tx: HandlerSender,
+    running: Arc<AtomicBool>,
+}
+
+impl CoreMidiBackend {
+    pub fn new(client_name: &str) -> Result<Self, MidiError> {
+        let client_name = client_name.to_string();
+        let (handler_tx, mut handler_rx) = notification_channel();
+        let (client_tx, client_rx) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        thread::spawn(move || {
+            let result = Client::new_with_notifications(
+                &client_name,
+                move |notification: &coremidi::Notification| {
+                    trace!("notification: {:?}", notification);
+                    handler_rx.notify(&notification_from(notification));
+                },
+            );
+            let failed = result.is_err();
+            client_tx.send(result).unwrap();
+            if failed {
+                return;
+            }
+
+            while thread_running.load(Ordering::Relaxed) {
+                let result = CFRunLoop::run_in_mode(
+                    unsafe { kCFRunLoopDefaultMode },
+                    Duration::from_millis(100),
+                    false,
+                );
+                if result == CFRunLoopRunResult::Finished {
+                    // no sources attached to the run loop (yet)
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        });
+
+        let client = client_rx
+            .recv()
+            .unwrap()
+            .map_err(os_error("MIDIClientCreate"))?;
+        Ok(CoreMidiBackend {
+            client,
+            clock: HostClock::new(),
+            handler_tx,
+            running,
+        })
+    }
+}
+
+/// The id of an endpoint is its unique ID, which CoreMIDI assigns to every
+/// endpoint and keeps across device reconnects and restarts.
+fn endpoint_id(endpoint: &impl AsRef<Object>) -> Option<EndpointId> {
+    endpoint
+        .as_ref()
+        .unique_id()
+        .map(|id| EndpointId(id.into()))
+}
+
+fn name(endpoint: &impl AsRef<Object>) -> String {
+    endpoint
+        .as_ref()
+        .name()
+        .unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// Properties of `endpoint`, the object of `endpoint_ref`.
+fn endpoint_info(
+    endpoint: &impl AsRef<Object>,
+    endpoint_ref: MIDIEndpointRef,
+) -> Option<EndpointInfo> {
+    let object = endpoint.as_ref();
+    let mut info = EndpointInfo::new(endpoint_id(endpoint)?, name(endpoint));
+    if let Some(display_name) = object.display_name() {
+        info.display_name = display_name;
+    }
+    // inherited from the entity or device unless set on the endpoint
+    info.manufacturer = object.get_property(&Properties::manufacturer()).ok();
+    info.model = object.get_property(&Properties::model()).ok();
+    info.unique_id = object.unique_id();
+    info.driver_owner = object.get_property(&Properties::driver_owner()).ok();
+    info.offline = object.get_property(&Properties::offline()).unwrap_or(false);
+    // virtual endpoints are not owned by an entity
+    let mut entity: MIDIObjectRef = 0;
+    let status = unsafe { MIDIEndpointGetEntity(endpoint_ref, &mut entity) };
+    info.is_virtual = status != 0 || entity == 0;
+    if !info.is_virtual {
+        info.entity = object_name(entity);
+        let mut device: MIDIObjectRef = 0;
+        if unsafe { MIDIEntityGetDevice(entity, &mut device) } == 0 && device != 0 {
+            info.device = object_name(device);
+        }
+    }
+    Some(info)
+}
+
+/// Name of an entity or device, which `coremidi` has no accessors for.
+fn object_name(object: MIDIObjectRef) -> Option<String> {
+    let mut string_ref: CFStringRef = std::ptr::null();
+    let status = unsafe { MIDIObjectGetStringProperty(object, kMIDIPropertyName, &mut string_ref) };
+    if status != 0 || string_ref.is_null() {
+        return None;
+    }
+    let name: CFString = unsafe { TCFType::wrap_under_create_rule(string_ref) };
+    Some(name.to_string())
+}
+
+/// Map the OSStatus of a failed `call` to a [`MidiError`].
+fn os_error(call: &'static str) -> impl FnOnce(i32) -> MidiError {
+    move |status| match status {
+        K_MIDI_OBJECT_NOT_FOUND => MidiError::DeviceGone,
+        status => MidiError::Backend { call, status },
+    }
+}
+
+/// Map a CoreMIDI notification, added and removed endpoints are found by the
+/// facade comparing the lists of endpoints.
+fn notification_from(notification: &coremidi::Notification) -> Notification {
+    match notification {
+        coremidi::Notification::PropertyChanged(info) => Notification::PropertyChanged {
+            name: info
+                .object
+                .as_ref()
+                .name()
+                .unwrap_or_else(|| "Unknown".to_string()),
+            property: info.property_name.clone(),
+        },
+        _ => Notification::SetupChanged,
+    }
+}
+
+impl Drop for CoreMidiBackend {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl MidiBackend for CoreMidiBackend {
+    fn sources(&self) -> Vec<(EndpointId, String)> {
+        Sources
+            .into_iter()
+            .filter_map(|source| Some((endpoint_id(&source)?, name(&source))))
+            .collect()
+    }
+
+    fn destinations(&self) -> Vec<(EndpointId, String)> {
+        Destinations
+            .into_iter()
+            .filter_map(|destination| Some((endpoint_id(&destination)?, name(&destination))))
+            .collect()
+    }
+
+    fn source_info(&self) -> Vec<EndpointInfo> {
+        (0..Sources::count())
+            .filter_map(|index| {
+                let endpoint_ref = unsafe { MIDIGetSource(index as ItemCount) };
+                endpoint_info(&Source::from_index(index)?, endpoint_ref)
+            })
+            .collect()
+    }
+
+    fn destination_info(&self) -> Vec<EndpointInfo> {
+        (0..Destinations::count())
+            .filter_map(|index| {
+                let endpoint_ref = unsafe { MIDIGetDestination(index as ItemCount) };
+                endpoint_info(&Destination::from_index(index)?, endpoint_ref)
+            })
+            .collect()
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn set_notification_handler(&mut self, handler: NotificationHandler) {
+        self.handler_tx.install(handler);
+    }
+
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        mut callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError> {
+        let id = source;
+        let source = Sources
+            .into_iter()
+            .find(|source| endpoint_id(source) == Some(id))
+            .ok_or(MidiError::UnknownEndpoint(id))?;
+        let clock = self.clock;
+        let input_port = self
+            .client
+            .input_port("input", move |packet_list| {
+                for packet in packet_list.iter() {
+                    callback(clock.since_epoch(packet.timestamp()), packet.data());
+                }
+            })
+            .map_err(os_error("MIDIInputPortCreate"))?;
+        input_port
+            .connect_source(&source)
+            .map_err(os_error("MIDIPortConnectSource"))?;
+        trace!(
+            "Connected to source: {}",
+            source.display_name().unwrap_or_default()
+        );
+        Ok(Box::new(CoreMidiInput { input_port }))
+    }
+
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError> {
+        let id = destination;
+        let destination = Destinations
+            .into_iter()
+            .find(|destination| endpoint_id(destination) == Some(id))
+            .ok_or(MidiError::UnknownEndpoint(id))?;
+        let output_port = self
+            .client
+            .output_port("output")
+            .map_err(os_error("MIDIOutputPortCreate"))?;
+        trace!(
+            "Connected to destination: {}",
+            destination.display_name().unwrap_or_default()
+        );
+        Ok(Box::new(CoreMidiOutput {
+            output_port,
+            destination,
+            clock: self.clock,
+        }))
+    }
+
+    fn create_virtual_source(
+        &mut self,
+        name: &str,
+    ) -> Result<(EndpointId, Box<dyn OutputConnection>), MidiError> {
+        let source = self
+            .client
+            .virtual_source(name)
+            .map_err(os_error("MIDISourceCreate"))?;
+        let id = endpoint_id(&*source).ok_or(MidiError::DeviceGone)?;
+        trace!("Created virtual source: {}", name);
+        Ok((
+            id,
+            Box::new(CoreMidiVirtualSource {
+                source,
+                clock: self.clock,
+            }),
+        ))
+    }
+
+    fn create_virtual_destination(
+        &mut self,
+        name: &str,
+        mut callback: InputCallback,
+    ) -> Result<(EndpointId, Box<dyn InputConnection>), MidiError> {
+        let clock = self.clock;
+        let destination = self
+            .client
+            .virtual_destination(name, move |packet_list| {
+                for packet in packet_list.iter() {
+                    callback(clock.since_epoch(packet.timestamp()), packet.data());
+                }
+            })
+            .map_err(os_error("MIDIDestinationCreate"))?;
+        let id = endpoint_id(&*destination).ok_or(MidiError::DeviceGone)?;
+        trace!("Created virtual destination: {}", name);
+        Ok((id, Box::new(CoreMidiVirtualDestination { destination })))
+    }
+}
+
+struct CoreMidiInput {
+    // the port is disposed, and thus disconnected, on drop
+    #[allow(dead_code)]
+    input_port: InputPort,
+}
+
+impl InputConnection for CoreMidiInput {}
+
+struct CoreMidiOutput {
+    output_port: OutputPort,
+    destination: Destination,
+    clock: HostClock,
+}
+
+impl OutputConnection for CoreMidiOutput {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.output_port
+            .send(&self.destination, &PacketBuffer::new(0, data))
+            .map_err(os_error("MIDISend"))
+    }
+
+    fn schedules(&self) -> bool {
+        true
+    }
+
+    fn send_at(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        let host_time = self.clock.host_time(timestamp);
+        self.output_port
+            .send(&self.destination, &PacketBuffer::new(host_time, data))
+            .map_err(os_error("MIDISend"))
+    }
+
+    fn flush(&mut self) -> Result<(), MidiError> {
+        self.destination
+            .flush()
+            .map_err(os_error("MIDIFlushOutput"))
+    }
+}
+
+struct CoreMidiVirtualSource {
+    source: VirtualSource,
+    clock: HostClock,
+}
+
+/// Data sent is received by the clients connected to the source.
+impl OutputConnection for CoreMidiVirtualSource {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.source
+            .received(&PacketBuffer::new(0, data))
+            .map_err(os_error("MIDIReceived"))
+    }
+
+    fn schedules(&self) -> bool {
+        true
+    }
+
+    fn send_at(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        let host_time = self.clock.host_time(timestamp);
+        self.source
+            .received(&PacketBuffer::new(host_time, data))
+            .map_err(os_error("MIDIReceived"))
+    }
+
+    fn flush(&mut self) -> Result<(), MidiError> {
+        self.source.flush().map_err(os_error("MIDIFlushOutput"))
+    }
+}
+
+struct CoreMidiVirtualDestination {
+    // the endpoint is disposed, and thus removed, on drop
+    #[allow(dead_code)]
+    destination: VirtualDestination,
+}
+
+impl InputConnection for CoreMidiVirtualDestination {}
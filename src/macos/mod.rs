@@ -1 +1,3 @@
-pub mod midi_con;
+mod core_midi;
+
+pub use core_midi::*;
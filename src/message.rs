@@ -0,0 +1,541 @@
+//! Typed MIDI 1.0 messages, a streaming parser turning raw MIDI data into
+//! messages, and an encoder turning messages into raw MIDI data.
+//!
+//! ```
+//! use rmidi::message::{MidiMessage, MidiParser};
+//!
+//! let mut parser = MidiParser::new();
+//! let mut messages = vec![];
+//! // note on, running status with an interleaved clock tick, note off
+//! parser.parse(&[0x90, 60, 100, 64, 0xf8, 100], |message| messages.push(message));
+//! parser.parse(&[0x80, 60, 0], |message| messages.push(message));
+//! assert_eq!(
+//!     messages,
+//!     vec![
+//!         MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 },
+//!         MidiMessage::TimingClock,
+//!         MidiMessage::NoteOn { channel: 0, note: 64, velocity: 100 },
+//!         MidiMessage::NoteOff { channel: 0, note: 60, velocity: 0 },
+//!     ]
+//! );
+//! ```
+
+use crate::error::MidiError;
+use log::trace;
+
+/// A MIDI 1.0 message.
+///
+/// Channels are zero based, i.e., `0..=15` for MIDI channels 1 to 16. Data
+/// bytes are 7 bit values, pitch bend and song position 14 bit values.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    /// Note on, a velocity of 0 being commonly used for note off.
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    /// Polyphonic key pressure.
+    PolyAftertouch {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// Channel pressure.
+    ChannelAftertouch {
+        channel: u8,
+        pressure: u8,
+    },
+    /// Pitch bend, 0x2000 being the center.
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    /// System exclusive, the data between `0xf0` and `0xf7`.
+    SysEx(Vec<u8>),
+    /// MIDI time code quarter frame.
+    MtcQuarterFrame(u8),
+    /// Song position pointer, in MIDI beats (sixteenth notes).
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    SystemReset,
+}
+
+impl MidiMessage {
+    /// Note off on `channel` 1 to 16.
+    pub fn note_off(channel: u8, note: u8, velocity: u8) -> Result<Self, MidiError> {
+        MidiMessage::NoteOff {
+            channel: channel_index(channel)?,
+            note,
+            velocity,
+        }
+        .validated()
+    }
+
+    /// Note on on `channel` 1 to 16.
+    pub fn note_on(channel: u8, note: u8, velocity: u8) -> Result<Self, MidiError> {
+        MidiMessage::NoteOn {
+            channel: channel_index(channel)?,
+            note,
+            velocity,
+        }
+        .validated()
+    }
+
+    /// Polyphonic key pressure on `channel` 1 to 16.
+    pub fn poly_aftertouch(channel: u8, note: u8, pressure: u8) -> Result<Self, MidiError> {
+        MidiMessage::PolyAftertouch {
+            channel: channel_index(channel)?,
+            note,
+            pressure,
+        }
+        .validated()
+    }
+
+    /// Control change on `channel` 1 to 16.
+    pub fn control_change(channel: u8, controller: u8, value: u8) -> Result<Self, MidiError> {
+        MidiMessage::ControlChange {
+            channel: channel_index(channel)?,
+            controller,
+            value,
+        }
+        .validated()
+    }
+
+    /// Program change on `channel` 1 to 16.
+    pub fn program_change(channel: u8, program: u8) -> Result<Self, MidiError> {
+        MidiMessage::ProgramChange {
+            channel: channel_index(channel)?,
+            program,
+        }
+        .validated()
+    }
+
+    /// Channel pressure on `channel` 1 to 16.
+    pub fn channel_aftertouch(channel: u8, pressure: u8) -> Result<Self, MidiError> {
+        MidiMessage::ChannelAftertouch {
+            channel: channel_index(channel)?,
+            pressure,
+        }
+        .validated()
+    }
+
+    /// Pitch bend on `channel` 1 to 16.
+    pub fn pitch_bend(channel: u8, value: u16) -> Result<Self, MidiError> {
+        MidiMessage::PitchBend {
+            channel: channel_index(channel)?,
+            value,
+        }
+        .validated()
+    }
+
+    /// MIDI channel 1 to 16 of channel messages.
+    pub fn channel_number(&self) -> Option<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyAftertouch { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelAftertouch { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel + 1),
+            _ => None,
+        }
+    }
+
+    /// Check that all fields are in range.
+    pub fn validate(&self) -> Result<(), MidiError> {
+        match self {
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            }
+            | MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*note as u16, 0x7f, "note")?;
+                check(*velocity as u16, 0x7f, "velocity")
+            }
+            MidiMessage::PolyAftertouch {
+                channel,
+                note,
+                pressure,
+            } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*note as u16, 0x7f, "note")?;
+                check(*pressure as u16, 0x7f, "pressure")
+            }
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*controller as u16, 0x7f, "controller")?;
+                check(*value as u16, 0x7f, "value")
+            }
+            MidiMessage::ProgramChange { channel, program } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*program as u16, 0x7f, "program")
+            }
+            MidiMessage::ChannelAftertouch { channel, pressure } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*pressure as u16, 0x7f, "pressure")
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                check(*channel as u16, 0x0f, "channel")?;
+                check(*value, 0x3fff, "pitch bend")
+            }
+            MidiMessage::SysEx(data) => data
+                .iter()
+                .try_for_each(|byte| check(*byte as u16, 0x7f, "SysEx data")),
+            MidiMessage::MtcQuarterFrame(value) => check(*value as u16, 0x7f, "quarter frame"),
+            MidiMessage::SongPosition(position) => check(*position, 0x3fff, "song position"),
+            MidiMessage::SongSelect(song) => check(*song as u16, 0x7f, "song"),
+            _ => Ok(()),
+        }
+    }
+
+    /// The raw MIDI data of the message.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MidiError> {
+        let mut data = vec![];
+        MidiEncoder::new(false).encode(self, &mut data)?;
+        Ok(data)
+    }
+
+    fn validated(self) -> Result<Self, MidiError> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Whether the message is a system realtime message, which may be
+    /// interleaved with other messages.
+    pub fn is_realtime(&self) -> bool {
+        matches!(
+            self,
+            MidiMessage::TimingClock
+                | MidiMessage::Start
+                | MidiMessage::Continue
+                | MidiMessage::Stop
+                | MidiMessage::ActiveSensing
+                | MidiMessage::SystemReset
+        )
+    }
+}
+
+/// Streaming parser of raw MIDI data.
+///
+/// Messages may be split over several calls of [`MidiParser::parse`], e.g.,
+/// a SysEx message arriving in several packets. Running status is supported,
+/// and system realtime bytes are reported as they arrive, also in the middle
+/// of other messages. Undefined status bytes, stray data bytes and SysEx
+/// messages interrupted by another status byte are dropped.
+#[derive(Clone, Debug, Default)]
+pub struct MidiParser {
+    /// Status of the message being parsed, kept after a channel message for
+    /// running status
+    status: Option<u8>,
+    /// Data bytes of the message being parsed
+    data: [u8; 2],
+    len: usize,
+    /// Data of the SysEx message being parsed
+    sysex: Option<Vec<u8>>,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse `data`, calling `f` for each complete message.
+    pub fn parse(&mut self, data: &[u8], mut f: impl FnMut(MidiMessage)) {
+        for byte in data {
+            if let Some(message) = self.push(*byte) {
+                f(message);
+            }
+        }
+    }
+
+    /// Parse a single byte, returning the message it completes, if any.
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            0xf8..=0xff => realtime(byte),
+            0xf7 => {
+                self.status = None;
+                self.sysex.take().map(MidiMessage::SysEx)
+            }
+            0x80..=0xf6 => {
+                if let Some(sysex) = self.sysex.take() {
+                    trace!(
+                        "SysEx of {} bytes interrupted by {:#04x}",
+                        sysex.len(),
+                        byte
+                    );
+                }
+                self.len = 0;
+                match byte {
+                    0xf0 => {
+                        self.status = None;
+                        self.sysex = Some(vec![]);
+                        None
+                    }
+                    0xf6 => {
+                        self.status = None;
+                        Some(MidiMessage::TuneRequest)
+                    }
+                    0xf4 | 0xf5 => {
+                        trace!("Undefined status byte {:#04x}", byte);
+                        self.status = None;
+                        None
+                    }
+                    _ => {
+                        self.status = Some(byte);
+                        None
+                    }
+                }
+            }
+            _ => {
+                if let Some(sysex) = &mut self.sysex {
+                    sysex.push(byte);
+                    return None;
+                }
+                let Some(status) = self.status else {
+                    trace!("Stray data byte {:#04x}", byte);
+                    return None;
+                };
+                self.data[self.len] = byte;
+                self.len += 1;
+                if self.len < data_len(status) {
+                    return None;
+                }
+                self.len = 0;
+                if status >= 0xf0 {
+                    // no running status for system common messages
+                    self.status = None;
+                }
+                Some(message(status, self.data))
+            }
+        }
+    }
+}
+
+/// Encoder of messages into raw MIDI data.
+///
+/// With running status, the status byte of a channel message is omitted if
+/// equal to that of the previous channel message, reducing the bandwidth on
+/// DIN (5-pin) connections. As the receiver must have seen the previous
+/// status byte, use one encoder for each stream of data sent at once.
+#[derive(Clone, Debug, Default)]
+pub struct MidiEncoder {
+    running_status: bool,
+    /// Status of the last channel message encoded, for running status
+    status: Option<u8>,
+}
+
+impl MidiEncoder {
+    pub fn new(running_status: bool) -> Self {
+        MidiEncoder {
+            running_status,
+            status: None,
+        }
+    }
+
+    /// Append the raw MIDI data of `message` to `data`, after checking that
+    /// all fields are in range.
+    pub fn encode(&mut self, message: &MidiMessage, data: &mut Vec<u8>) -> Result<(), MidiError> {
+        message.validate()?;
+        match message {
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => self.channel_message(0x80 | channel, &[*note, *velocity], data),
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => self.channel_message(0x90 | channel, &[*note, *velocity], data),
+            MidiMessage::PolyAftertouch {
+                channel,
+                note,
+                pressure,
+            } => self.channel_message(0xa0 | channel, &[*note, *pressure], data),
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => self.channel_message(0xb0 | channel, &[*controller, *value], data),
+            MidiMessage::ProgramChange { channel, program } => {
+                self.channel_message(0xc0 | channel, &[*program], data)
+            }
+            MidiMessage::ChannelAftertouch { channel, pressure } => {
+                self.channel_message(0xd0 | channel, &[*pressure], data)
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                self.channel_message(0xe0 | channel, &lsb_msb(*value), data)
+            }
+            MidiMessage::SysEx(sysex) => {
+                self.status = None;
+                data.push(0xf0);
+                data.extend_from_slice(sysex);
+                data.push(0xf7);
+            }
+            MidiMessage::MtcQuarterFrame(value) => self.system_common(&[0xf1, *value], data),
+            MidiMessage::SongPosition(position) => {
+                let [lsb, msb] = lsb_msb(*position);
+                self.system_common(&[0xf2, lsb, msb], data)
+            }
+            MidiMessage::SongSelect(song) => self.system_common(&[0xf3, *song], data),
+            MidiMessage::TuneRequest => self.system_common(&[0xf6], data),
+            // realtime messages leave running status as is
+            MidiMessage::TimingClock => data.push(0xf8),
+            MidiMessage::Start => data.push(0xfa),
+            MidiMessage::Continue => data.push(0xfb),
+            MidiMessage::Stop => data.push(0xfc),
+            MidiMessage::ActiveSensing => data.push(0xfe),
+            MidiMessage::SystemReset => data.push(0xff),
+        }
+        Ok(())
+    }
+
+    fn channel_message(&mut self, status: u8, bytes: &[u8], data: &mut Vec<u8>) {
+        if !self.running_status || self.status != Some(status) {
+            data.push(status);
+        }
+        self.status = Some(status);
+        data.extend_from_slice(bytes);
+    }
+
+    fn system_common(&mut self, bytes: &[u8], data: &mut Vec<u8>) {
+        self.status = None;
+        data.extend_from_slice(bytes);
+    }
+}
+
+/// Zero based channel of MIDI `channel` 1 to 16.
+fn channel_index(channel: u8) -> Result<u8, MidiError> {
+    if (1..=16).contains(&channel) {
+        Ok(channel - 1)
+    } else {
+        Err(MidiError::InvalidMessage {
+            field: "channel",
+            value: channel as u32,
+        })
+    }
+}
+
+/// Check `value` of `field` to be at most `max`.
+fn check(value: u16, max: u16, field: &'static str) -> Result<(), MidiError> {
+    if value <= max {
+        Ok(())
+    } else {
+        Err(MidiError::InvalidMessage {
+            field,
+            value: value as u32,
+        })
+    }
+}
+
+/// Least and most significant 7 bits of a 14 bit value.
+fn lsb_msb(value: u16) -> [u8; 2] {
+    [(value & 0x7f) as u8, (value >> 7) as u8]
+}
+
+/// Number of data bytes of messages of `status`.
+pub(crate) fn data_len(status: u8) -> usize {
+    match status {
+        0xc0..=0xdf | 0xf1 | 0xf3 => 1,
+        _ => 2,
+    }
+}
+
+/// The message of a complete channel or system common message.
+pub(crate) fn message(status: u8, data: [u8; 2]) -> MidiMessage {
+    let channel = status & 0x0f;
+    let [data1, data2] = data;
+    match status & 0xf0 {
+        0x80 => MidiMessage::NoteOff {
+            channel,
+            note: data1,
+            velocity: data2,
+        },
+        0x90 => MidiMessage::NoteOn {
+            channel,
+            note: data1,
+            velocity: data2,
+        },
+        0xa0 => MidiMessage::PolyAftertouch {
+            channel,
+            note: data1,
+            pressure: data2,
+        },
+        0xb0 => MidiMessage::ControlChange {
+            channel,
+            controller: data1,
+            value: data2,
+        },
+        0xc0 => MidiMessage::ProgramChange {
+            channel,
+            program: data1,
+        },
+        0xd0 => MidiMessage::ChannelAftertouch {
+            channel,
+            pressure: data1,
+        },
+        0xe0 => MidiMessage::PitchBend {
+            channel,
+            value: u14(data1, data2),
+        },
+        _ => match status {
+            0xf1 => MidiMessage::MtcQuarterFrame(data1),
+            0xf2 => MidiMessage::SongPosition(u14(data1, data2)),
+            0xf3 => MidiMessage::SongSelect(data1),
+            _ => unreachable!("no data bytes for status {:#04x}", status),
+        },
+    }
+}
+
+/// The message of a system realtime byte.
+fn realtime(byte: u8) -> Option<MidiMessage> {
+    match byte {
+        0xf8 => Some(MidiMessage::TimingClock),
+        0xfa => Some(MidiMessage::Start),
+        0xfb => Some(MidiMessage::Continue),
+        0xfc => Some(MidiMessage::Stop),
+        0xfe => Some(MidiMessage::ActiveSensing),
+        0xff => Some(MidiMessage::SystemReset),
+        _ => {
+            trace!("Undefined status byte {:#04x}", byte);
+            None
+        }
+    }
+}
+
+/// 14 bit value of least and most significant 7 bits.
+fn u14(lsb: u8, msb: u8) -> u16 {
+    (msb as u16) << 7 | lsb as u16
+}
@@ -0,0 +1,973 @@
+use crate::backend::{
+    InputCallback, InputConnection, MidiBackend, NotificationHandler, OutputConnection,
+};
+use crate::builder::MidiConBuilder;
+use crate::channel::Delivery;
+use crate::error::MidiError;
+use crate::message::{MidiEncoder, MidiMessage, MidiParser};
+use crate::scheduler::Scheduler;
+use crate::sysex::{SysExAssembler, SysExLimits};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::marker::Send;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+pub struct MidiCon {
+    pub backend: Box<dyn MidiBackend>,
+    pub opt_notification_callback: Option<Arc<NotificationHandler>>,
+    /// Sources as of the last notification
+    pub sources: Vec<(EndpointId, String)>,
+    /// Destinations as of the last notification
+    pub destinations: Vec<(EndpointId, String)>,
+    /// Connected sources, by the id they were connected with
+    pub in_ports: HashMap<EndpointId, InPort>,
+    /// Connected destinations, by the id they were connected with
+    pub out_ports: HashMap<EndpointId, OutPort>,
+    /// Limits of SysEx messages reassembled from incoming data
+    pub sysex_limits: SysExLimits,
+    /// Delivery of sources connected through a channel
+    pub delivery: Delivery,
+    /// Non-blocking receivers of notifications besides the callback, e.g.,
+    /// notification streams, returning `false` once gone
+    pub(crate) notification_subscribers: Vec<NotificationSubscriber>,
+    /// Scheduler for destinations without native scheduling, started on demand
+    scheduler: Option<Scheduler>,
+}
+
+impl MidiCon {
+    /// Key of the source connected as, or currently enumerated as, `id`.
+    fn in_port_key(&self, id: EndpointId) -> Option<EndpointId> {
+        self.in_ports
+            .iter()
+            .find(|(key, port)| **key == id || port.endpoint == id)
+            .map(|(key, _)| *key)
+    }
+
+    /// Key of the destination connected as, or currently enumerated as, `id`.
+    fn out_port_key(&self, id: EndpointId) -> Option<EndpointId> {
+        self.out_ports
+            .iter()
+            .find(|(key, port)| **key == id || port.endpoint == id)
+            .map(|(key, _)| *key)
+    }
+
+    /// Endpoints of the sources currently connected.
+    pub(crate) fn connected_sources(&self) -> Vec<EndpointId> {
+        self.in_ports
+            .values()
+            .filter(|port| port.connection.is_some())
+            .map(|port| port.endpoint)
+            .collect()
+    }
+
+    /// Endpoints of the destinations currently connected.
+    pub(crate) fn connected_destinations(&self) -> Vec<EndpointId> {
+        self.out_ports
+            .values()
+            .filter(|port| port.connection.is_some())
+            .map(|port| port.endpoint)
+            .collect()
+    }
+
+    /// Pass `notifications` to the subscribers, dropping those gone.
+    fn notify_subscribers(&mut self, notifications: &[Notification]) {
+        self.notification_subscribers
+            .retain(|subscriber| notifications.iter().all(subscriber));
+    }
+}
+
+/// Non-blocking receiver of notifications, invoked while holding the lock.
+pub(crate) type NotificationSubscriber = Box<dyn Fn(&Notification) -> bool + Send + 'static>;
+
+/// Callback for incoming data of a connected source, with the time it was
+/// received, see [`ArcMutexMidiCon::now`].
+pub type SourceCallback = Box<dyn Fn(Duration, &[u8], &ArcMutexMidiCon) + Send + 'static>;
+
+/// A connected source, kept while detached to be reconnected once re-attached.
+pub struct InPort {
+    /// Id of the source as currently enumerated
+    pub endpoint: EndpointId,
+    /// Name of the source, to find it if re-attached under another id
+    pub name: String,
+    pub callback: Arc<Mutex<SourceCallback>>,
+    /// The open connection, `None` while detached
+    pub connection: Option<Box<dyn InputConnection>>,
+}
+
+/// Output connection of a destination, shared so data is sent without holding
+/// the lock of [`MidiCon`].
+pub type SharedOutput = Arc<Mutex<Box<dyn OutputConnection>>>;
+
+/// A connected destination, kept while detached to be reconnected once re-attached.
+pub struct OutPort {
+    /// Id of the destination as currently enumerated
+    pub endpoint: EndpointId,
+    /// Name of the destination, to find it if re-attached under another id
+    pub name: String,
+    /// The open connection, `None` while detached
+    pub connection: Option<SharedOutput>,
+    /// Whether messages are sent with running status
+    pub running_status: bool,
+}
+
+/// Identity of a source or destination, provided by the backend.
+///
+/// Unlike the index, which shifts as devices are added or removed, the id of
+/// an endpoint stays the same while the endpoint exists. It is derived from
+/// the CoreMIDI unique ID, the ALSA client and port numbers, or the WinMM
+/// device name and its ordinal among devices of the same name.
+///
+/// Serialized as 16 hex digits, as not all formats (e.g., TOML) support the
+/// full range of `u64`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct EndpointId(pub u64);
+
+impl From<EndpointId> for String {
+    fn from(id: EndpointId) -> Self {
+        format!("{:016x}", id.0)
+    }
+}
+
+impl TryFrom<String> for EndpointId {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        u64::from_str_radix(&s, 16).map(EndpointId)
+    }
+}
+
+/// Properties of a source or destination, as far as provided by the backend.
+///
+/// CoreMIDI provides all of them. The ALSA sequencer has no manufacturer,
+/// unique ID, driver owner or entity, its device is the client of the port.
+/// Other backends provide only the name.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointInfo {
+    pub id: EndpointId,
+    /// Name as listed by [`ArcMutexMidiCon::list_sources`] and
+    /// [`ArcMutexMidiCon::list_destinations`]
+    pub name: String,
+    /// Name for display, e.g., including the device name
+    pub display_name: String,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    /// CoreMIDI unique ID
+    pub unique_id: Option<u32>,
+    /// Driver owning the device of the endpoint
+    pub driver_owner: Option<String>,
+    /// Device is temporarily absent
+    pub offline: bool,
+    /// Endpoint created by software rather than a device, e.g., by
+    /// [`ArcMutexMidiCon::create_virtual_source`]
+    pub is_virtual: bool,
+    /// Name of the entity grouping the endpoint with others of the device
+    pub entity: Option<String>,
+    /// Name of the device of the endpoint
+    pub device: Option<String>,
+    /// Endpoint is connected through this `ArcMutexMidiCon`
+    pub connected: bool,
+}
+
+impl EndpointInfo {
+    /// Properties of an endpoint of which only the name is known.
+    pub fn new(id: EndpointId, name: String) -> Self {
+        EndpointInfo {
+            id,
+            display_name: name.clone(),
+            name,
+            manufacturer: None,
+            model: None,
+            unique_id: None,
+            driver_owner: None,
+            offline: false,
+            is_virtual: false,
+            entity: None,
+            device: None,
+            connected: false,
+        }
+    }
+}
+
+/// Notification of changes to the MIDI setup.
+///
+/// Indexes and names are those of [`ArcMutexMidiCon::list_sources`] and
+/// [`ArcMutexMidiCon::list_destinations`], for removed endpoints as listed
+/// before the removal and for added endpoints as listed after the addition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Notification {
+    SourceAdded {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    SourceRemoved {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    DestinationAdded {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    DestinationRemoved {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    /// A property of an endpoint or device changed, `name` being the name of the
+    /// endpoint or device and `property` the name of the property.
+    PropertyChanged { name: String, property: String },
+    /// A detached source was re-attached and its connection restored, `id`
+    /// being the id it was connected with.
+    SourceReconnected {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    /// A detached destination was re-attached and its connection restored, `id`
+    /// being the id it was connected with.
+    DestinationReconnected {
+        id: EndpointId,
+        index: usize,
+        name: String,
+    },
+    /// The setup changed, sent by backends after any other change.
+    SetupChanged,
+    /// Incoming data of a source was dropped, e.g., an incomplete SysEx
+    /// message, `id` being the id the source was connected with.
+    InputError { id: EndpointId, error: MidiError },
+}
+
+#[derive(Clone)]
+pub struct ArcMutexMidiCon(pub Arc<Mutex<MidiCon>>);
+
+impl ArcMutexMidiCon {
+    /// Create MidiConnections using the native backend of the platform, see
+    /// [`MidiConBuilder`] for further options.
+    pub fn new() -> Result<Self, MidiError> {
+        MidiConBuilder::new().build()
+    }
+
+    /// Create MidiConnections on top of the given backend.
+    pub fn with_backend(backend: Box<dyn MidiBackend>) -> Self {
+        Self::with_settings(backend, None, SysExLimits::default(), Delivery::default())
+    }
+
+    /// Create MidiConnections, the notification callback being in place before
+    /// the first notification of the backend.
+    pub(crate) fn with_settings(
+        backend: Box<dyn MidiBackend>,
+        opt_notification_callback: Option<Arc<NotificationHandler>>,
+        sysex_limits: SysExLimits,
+        delivery: Delivery,
+    ) -> Self {
+        let sources = backend.sources();
+        let destinations = backend.destinations();
+        let arc_mutex_midi_con = ArcMutexMidiCon(Arc::new(Mutex::new(MidiCon {
+            backend,
+            opt_notification_callback,
+            sources,
+            destinations,
+            in_ports: HashMap::new(),
+            out_ports: HashMap::new(),
+            sysex_limits,
+            delivery,
+            notification_subscribers: vec![],
+            scheduler: None,
+        })));
+        // the backend is owned by the connection, so its handler must not keep
+        // the connection alive
+        let weak = Arc::downgrade(&arc_mutex_midi_con.0);
+
+        arc_mutex_midi_con
+            .0
+            .lock()
+            .unwrap()
+            .backend
+            .set_notification_handler(Box::new(move |notification: &Notification| {
+                if let Some(midi_con) = weak.upgrade() {
+                    ArcMutexMidiCon(midi_con).update_connections(notification);
+                }
+            }));
+        arc_mutex_midi_con
+    }
+
+    /// Time elapsed since the session epoch, the timebase of the timestamps of
+    /// incoming data.
+    pub fn now(&self) -> Duration {
+        self.0.lock().unwrap().backend.now()
+    }
+
+    /// Set the callback for notifications of changes to the MIDI setup.
+    ///
+    /// The callback is never invoked while holding the lock, so it may call
+    /// any method of `ArcMutexMidiCon`, even ones causing further
+    /// notifications.
+    pub fn set_notification_callback(&self, cb: impl Fn(&Notification) + Send + Sync + 'static) {
+        let midi_con = &mut self.0.lock().unwrap();
+        midi_con.opt_notification_callback = Some(Arc::new(Box::new(cb)));
+    }
+
+    /// Handle a notification from the backend.
+    ///
+    /// Sources and destinations added or removed since the last notification are
+    /// reported to the notification callback, followed by restored connections
+    /// and the notification itself. The callback is invoked without holding the
+    /// lock, so it may call back into `ArcMutexMidiCon`, e.g., to refresh its
+    /// list of sources.
+    ///
+    /// Connections of removed sources and destinations are closed, but kept to
+    /// be restored once re-attached, matching the device by its id, or failing
+    /// that, by its name.
+    pub fn update_connections(&self, notification: &Notification) {
+        trace!("notification: {:?}", notification);
+        let (opt_cb, notifications) = {
+            let midi_con = &mut *self.0.lock().unwrap();
+            let sources = midi_con.backend.sources();
+            let destinations = midi_con.backend.destinations();
+
+            let mut notifications = diff_endpoints(
+                &midi_con.sources,
+                &sources,
+                |id, index, name| Notification::SourceRemoved { id, index, name },
+                |id, index, name| Notification::SourceAdded { id, index, name },
+            );
+            notifications.extend(diff_endpoints(
+                &midi_con.destinations,
+                &destinations,
+                |id, index, name| Notification::DestinationRemoved { id, index, name },
+                |id, index, name| Notification::DestinationAdded { id, index, name },
+            ));
+            notifications.extend(self.reconnect_sources(midi_con, &sources));
+            notifications.extend(reconnect_destinations(midi_con, &destinations));
+            notifications.push(notification.clone());
+            midi_con.notify_subscribers(&notifications);
+
+            midi_con.sources = sources;
+            midi_con.destinations = destinations;
+            (midi_con.opt_notification_callback.clone(), notifications)
+        };
+
+        if let Some(cb) = opt_cb {
+            for notification in &notifications {
+                cb(notification);
+            }
+        }
+    }
+
+    /// Set the limits of SysEx messages reassembled from incoming data, for
+    /// sources connected (or reconnected) afterwards.
+    pub fn set_sysex_limits(&self, limits: SysExLimits) {
+        self.0.lock().unwrap().sysex_limits = limits;
+    }
+
+    /// Report a notification to the notification callback, without holding
+    /// the lock.
+    fn notify(&self, notification: &Notification) {
+        let opt_cb = {
+            let midi_con = &mut *self.0.lock().unwrap();
+            midi_con.notify_subscribers(std::slice::from_ref(notification));
+            midi_con.opt_notification_callback.clone()
+        };
+        if let Some(cb) = opt_cb {
+            cb(notification);
+        }
+    }
+
+    /// Input callback of the backend, calling the `callback` of the source
+    /// connected as `key()` with whole SysEx messages.
+    ///
+    /// Dropped SysEx messages are reported as [`Notification::InputError`].
+    /// Only a weak reference to the connection is kept, as the backend input
+    /// calling it is owned by the connection.
+    pub(crate) fn input_callback(
+        &self,
+        key: impl Fn() -> EndpointId + Send + 'static,
+        limits: SysExLimits,
+        callback: &Arc<Mutex<SourceCallback>>,
+    ) -> InputCallback {
+        let weak: Weak<Mutex<MidiCon>> = Arc::downgrade(&self.0);
+        let callback = callback.clone();
+        let mut assembler = SysExAssembler::new(limits);
+        Box::new(move |timestamp, data| {
+            let Some(midi_con) = weak.upgrade() else {
+                return;
+            };
+            let mc = ArcMutexMidiCon(midi_con);
+            let mut errors = vec![];
+            {
+                let callback = callback.lock().unwrap();
+                assembler.push(
+                    timestamp,
+                    data,
+                    |timestamp, data| callback(timestamp, data, &mc),
+                    |error| errors.push(error),
+                );
+            }
+            for error in errors {
+                let id = key();
+                trace!("Input error of source {:?}: {}", id, error);
+                mc.notify(&Notification::InputError { id, error });
+            }
+        })
+    }
+
+    /// Close connections of detached sources, and reopen re-attached ones.
+    fn reconnect_sources(
+        &self,
+        midi_con: &mut MidiCon,
+        sources: &[(EndpointId, String)],
+    ) -> Vec<Notification> {
+        let mut notifications = vec![];
+        let mut connected = midi_con.connected_sources();
+        for (key, port) in midi_con.in_ports.iter_mut() {
+            if port.connection.is_some() {
+                if !sources.iter().any(|(id, _)| *id == port.endpoint) {
+                    trace!("Source detached: {:?}", key);
+                    port.connection = None;
+                }
+                continue;
+            }
+            let Some((index, (id, name))) =
+                find_endpoint(sources, port.endpoint, &port.name, |id| {
+                    connected.contains(&id)
+                })
+            else {
+                continue;
+            };
+            let key = *key;
+            match midi_con.backend.open_input(
+                *id,
+                self.input_callback(move || key, midi_con.sysex_limits, &port.callback),
+            ) {
+                Ok(input) => {
+                    trace!("Source reconnected: {:?} as {:?}", key, id);
+                    connected.push(*id);
+                    port.endpoint = *id;
+                    port.connection = Some(input);
+                    notifications.push(Notification::SourceReconnected {
+                        id: key,
+                        index,
+                        name: name.clone(),
+                    });
+                }
+                Err(err) => trace!("Failed to reconnect source {:?}: {}", key, err),
+            }
+        }
+        notifications
+    }
+
+    /// Connect to a MIDI source by its id with a callback for incoming data and
+    /// the time it was received
+    pub fn connect_source(
+        &self,
+        source: EndpointId,
+        cb: impl Fn(Duration, &[u8], &ArcMutexMidiCon) + Send + 'static,
+    ) -> Result<(), MidiError> {
+        let midi_con = &mut self.0.lock().unwrap();
+        if midi_con.in_port_key(source).is_some() {
+            return Err(MidiError::AlreadyConnected(source));
+        }
+        let (_, name) = midi_con
+            .backend
+            .sources()
+            .into_iter()
+            .find(|(id, _)| *id == source)
+            .ok_or(MidiError::UnknownEndpoint(source))?;
+        trace!("Connecting to source: {:?}", source);
+        let callback: Arc<Mutex<SourceCallback>> = Arc::new(Mutex::new(Box::new(cb)));
+        let input_callback = self.input_callback(move || source, midi_con.sysex_limits, &callback);
+        let input = midi_con.backend.open_input(source, input_callback)?;
+        trace!("Connected to source: {:?}", source);
+        midi_con.in_ports.insert(
+            source,
+            InPort {
+                endpoint: source,
+                name,
+                callback,
+                connection: Some(input),
+            },
+        );
+        Ok(())
+    }
+
+    /// Connect to a MIDI source by its index with a callback for incoming data
+    pub fn connect_source_by_index(
+        &self,
+        source_index: usize,
+        cb: impl Fn(Duration, &[u8], &ArcMutexMidiCon) + Send + 'static,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_index(source_index)?;
+        self.connect_source(source, cb)?;
+        Ok(source)
+    }
+
+    /// Connect to a MIDI source by its name with a callback for incoming data
+    pub fn connect_source_by_name(
+        &self,
+        source_name: &str,
+        cb: impl Fn(Duration, &[u8], &ArcMutexMidiCon) + Send + 'static,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_name(source_name)?;
+        self.connect_source(source, cb)?;
+        Ok(source)
+    }
+
+    /// Id of the source currently at `source_index`.
+    pub(crate) fn source_by_index(&self, source_index: usize) -> Result<EndpointId, MidiError> {
+        let (source, _) = self
+            .0
+            .lock()
+            .unwrap()
+            .backend
+            .sources()
+            .into_iter()
+            .nth(source_index)
+            .ok_or(MidiError::UnknownIndex(source_index))?;
+        Ok(source)
+    }
+
+    /// Id of the first source named `source_name`.
+    pub(crate) fn source_by_name(&self, source_name: &str) -> Result<EndpointId, MidiError> {
+        let (source, _) = self
+            .0
+            .lock()
+            .unwrap()
+            .backend
+            .sources()
+            .into_iter()
+            .find(|(_, name)| name == source_name)
+            .ok_or_else(|| MidiError::UnknownName(source_name.to_string()))?;
+        Ok(source)
+    }
+
+    /// Connect to a MIDI source by its id with a callback for incoming messages
+    /// and the time they were received
+    ///
+    /// Incoming data is parsed by a [`MidiParser`] kept for the connection, so
+    /// messages split over several packets, running status and interleaved
+    /// realtime messages are handled.
+    pub fn connect_source_messages(
+        &self,
+        source: EndpointId,
+        cb: impl Fn(Duration, &MidiMessage, &ArcMutexMidiCon) + Send + 'static,
+    ) -> Result<(), MidiError> {
+        let parser = Mutex::new(MidiParser::new());
+        self.connect_source(source, move |timestamp, data, mc| {
+            parser
+                .lock()
+                .unwrap()
+                .parse(data, |message| cb(timestamp, &message, mc))
+        })
+    }
+
+    /// List available MIDI sources by their ids and names, in enumeration order
+    pub fn list_sources(&self) -> Vec<(EndpointId, bool, String)> {
+        trace!("Listing MIDI Sources:");
+        let midi_con = &self.0.lock().unwrap();
+        midi_con
+            .backend
+            .sources()
+            .into_iter()
+            .map(|(id, name)| (id, midi_con.in_port_key(id).is_some(), name))
+            .collect()
+    }
+
+    /// List available MIDI destinations by their ids and names, in enumeration order
+    pub fn list_destinations(&self) -> Vec<(EndpointId, bool, String)> {
+        trace!("Listing MIDI Destinations:");
+        let midi_con = &self.0.lock().unwrap();
+        midi_con
+            .backend
+            .destinations()
+            .into_iter()
+            .map(|(id, name)| (id, midi_con.out_port_key(id).is_some(), name))
+            .collect()
+    }
+
+    /// List available MIDI sources with their properties, in enumeration order
+    pub fn list_source_info(&self) -> Vec<EndpointInfo> {
+        let midi_con = &self.0.lock().unwrap();
+        midi_con
+            .backend
+            .source_info()
+            .into_iter()
+            .map(|info| EndpointInfo {
+                connected: midi_con.in_port_key(info.id).is_some(),
+                ..info
+            })
+            .collect()
+    }
+
+    /// List available MIDI destinations with their properties, in enumeration
+    /// order
+    pub fn list_destination_info(&self) -> Vec<EndpointInfo> {
+        let midi_con = &self.0.lock().unwrap();
+        midi_con
+            .backend
+            .destination_info()
+            .into_iter()
+            .map(|info| EndpointInfo {
+                connected: midi_con.out_port_key(info.id).is_some(),
+                ..info
+            })
+            .collect()
+    }
+
+    /// Connect to a MIDI destination by its id
+    pub fn connect_destination(&self, destination: EndpointId) -> Result<(), MidiError> {
+        let midi_con = &mut self.0.lock().unwrap();
+        if midi_con.out_port_key(destination).is_some() {
+            return Err(MidiError::AlreadyConnected(destination));
+        }
+        let (_, name) = midi_con
+            .backend
+            .destinations()
+            .into_iter()
+            .find(|(id, _)| *id == destination)
+            .ok_or(MidiError::UnknownEndpoint(destination))?;
+        trace!("Connecting to destination: {:?}", destination);
+        let output = midi_con.backend.open_output(destination)?;
+        midi_con.out_ports.insert(
+            destination,
+            OutPort {
+                endpoint: destination,
+                name,
+                connection: Some(Arc::new(Mutex::new(output))),
+                running_status: false,
+            },
+        );
+        trace!("Connected to destination: {:?}", destination);
+        Ok(())
+    }
+
+    /// Connect to a MIDI destination by its index
+    pub fn connect_destination_by_index(
+        &self,
+        destination_index: usize,
+    ) -> Result<EndpointId, MidiError> {
+        let (destination, _) = self
+            .0
+            .lock()
+            .unwrap()
+            .backend
+            .destinations()
+            .into_iter()
+            .nth(destination_index)
+            .ok_or(MidiError::UnknownIndex(destination_index))?;
+        self.connect_destination(destination)?;
+        Ok(destination)
+    }
+
+    /// Send MIDI data to a connected destination by its id
+    ///
+    /// Data sent while the destination is detached is dropped.
+    pub fn send(&self, destination: EndpointId, data: &[u8]) -> Result<(), MidiError> {
+        let (key, opt_output) = self.output(destination)?;
+        let Some(output) = opt_output else {
+            trace!("Dropped MIDI data to detached destination {:?}", key);
+            return Ok(());
+        };
+        let result = output.lock().unwrap().send(data);
+        match result {
+            // detached before the backend notified us
+            Err(MidiError::DeviceGone) => self.detach_output(key, &output),
+            result => result?,
+        }
+        trace!(
+            "Sent MIDI data to destination {:?}: {:?}",
+            destination, data
+        );
+        Ok(())
+    }
+
+    /// Schedule MIDI data for a connected destination by its id, to be
+    /// delivered at `timestamp`, see [`ArcMutexMidiCon::now`]
+    ///
+    /// Data is scheduled natively where supported (CoreMIDI, ALSA), and by a
+    /// scheduler thread otherwise. Data scheduled in the past is delivered right
+    /// away, data scheduled while the destination is detached is dropped.
+    pub fn send_at(
+        &self,
+        destination: EndpointId,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> Result<(), MidiError> {
+        let (key, opt_output) = self.output(destination)?;
+        let Some(output) = opt_output else {
+            trace!("Dropped MIDI data to detached destination {:?}", key);
+            return Ok(());
+        };
+        let opt_result = {
+            let output = &mut *output.lock().unwrap();
+            output.schedules().then(|| output.send_at(timestamp, data))
+        };
+        if let Some(result) = opt_result {
+            match result {
+                Err(MidiError::DeviceGone) => self.detach_output(key, &output),
+                result => result?,
+            }
+        } else {
+            let midi_con = &mut *self.0.lock().unwrap();
+            let now = Instant::now();
+            let epoch = now.checked_sub(midi_con.backend.now()).unwrap_or(now);
+            midi_con
+                .scheduler
+                .get_or_insert_with(|| Scheduler::new(Arc::downgrade(&self.0), epoch))
+                .schedule(key, timestamp, data);
+        }
+        trace!(
+            "Scheduled MIDI data to destination {:?} at {:?}: {:?}",
+            destination, timestamp, data
+        );
+        Ok(())
+    }
+
+    /// Drop MIDI data scheduled for a connected destination by its id, which
+    /// has not been delivered yet
+    ///
+    /// Data due by the time of the call may still be sent, as it may be on its
+    /// way already.
+    pub fn flush(&self, destination: EndpointId) -> Result<(), MidiError> {
+        let (key, opt_output) = self.output(destination)?;
+        if let Some(scheduler) = &self.0.lock().unwrap().scheduler {
+            scheduler.flush(key);
+        }
+        if let Some(output) = opt_output {
+            output.lock().unwrap().flush()?;
+        }
+        trace!("Flushed destination: {:?}", destination);
+        Ok(())
+    }
+
+    /// Send a SysEx message to a connected destination by its id, in chunks of
+    /// at most `chunk_size` bytes
+    ///
+    /// `sysex` is the data between `0xf0` and `0xf7`, as for
+    /// [`MidiMessage::SysEx`]. Slow hardware may need a `delay` between the
+    /// chunks, the chunks are then scheduled by [`ArcMutexMidiCon::send_at`], so
+    /// the call returns right away.
+    pub fn send_sysex(
+        &self,
+        destination: EndpointId,
+        sysex: &[u8],
+        chunk_size: usize,
+        delay: Option<Duration>,
+    ) -> Result<(), MidiError> {
+        let data = MidiMessage::SysEx(sysex.to_vec()).to_bytes()?;
+        let chunks = data.chunks(chunk_size.max(1));
+        match delay {
+            Some(delay) => {
+                let start = self.now();
+                for (i, chunk) in chunks.enumerate() {
+                    self.send_at(destination, start + delay * i as u32, chunk)?;
+                }
+            }
+            None => {
+                for chunk in chunks {
+                    self.send(destination, chunk)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a MIDI message to a connected destination by its id
+    ///
+    /// Fails with [`MidiError::InvalidMessage`] if a field of the message is out
+    /// of range.
+    pub fn send_message(
+        &self,
+        destination: EndpointId,
+        message: &MidiMessage,
+    ) -> Result<(), MidiError> {
+        self.send_messages(destination, std::slice::from_ref(message))
+    }
+
+    /// Send MIDI messages to a connected destination by its id, at once
+    ///
+    /// With running status enabled for the destination, repeated status bytes
+    /// are omitted.
+    pub fn send_messages(
+        &self,
+        destination: EndpointId,
+        messages: &[MidiMessage],
+    ) -> Result<(), MidiError> {
+        let running_status = {
+            let midi_con = &self.0.lock().unwrap();
+            let key = midi_con
+                .out_port_key(destination)
+                .ok_or(MidiError::NotConnected(destination))?;
+            midi_con.out_ports[&key].running_status
+        };
+        let mut encoder = MidiEncoder::new(running_status);
+        let mut data = vec![];
+        for message in messages {
+            encoder.encode(message, &mut data)?;
+        }
+        self.send(destination, &data)
+    }
+
+    /// Enable or disable running status for messages sent to a connected
+    /// destination by its id
+    ///
+    /// Running status reduces the bandwidth used on DIN (5-pin) connections,
+    /// it is applied within each call of [`ArcMutexMidiCon::send_messages`].
+    pub fn set_running_status(
+        &self,
+        destination: EndpointId,
+        enabled: bool,
+    ) -> Result<(), MidiError> {
+        let midi_con = &mut self.0.lock().unwrap();
+        let key = midi_con
+            .out_port_key(destination)
+            .ok_or(MidiError::NotConnected(destination))?;
+        midi_con.out_ports.get_mut(&key).unwrap().running_status = enabled;
+        Ok(())
+    }
+
+    /// Key and output connection of a connected destination, the connection
+    /// being `None` while detached.
+    ///
+    /// Data is sent through the connection after releasing the lock, as a
+    /// backend may deliver it right away, e.g., the mock backend to a virtual
+    /// destination, whose callback may call back into `ArcMutexMidiCon`.
+    fn output(
+        &self,
+        destination: EndpointId,
+    ) -> Result<(EndpointId, Option<SharedOutput>), MidiError> {
+        let midi_con = &self.0.lock().unwrap();
+        let key = midi_con
+            .out_port_key(destination)
+            .ok_or(MidiError::NotConnected(destination))?;
+        Ok((key, midi_con.out_ports[&key].connection.clone()))
+    }
+
+    /// Close the `output` of the destination connected as `key` found
+    /// detached, unless closed or replaced meanwhile.
+    fn detach_output(&self, key: EndpointId, output: &SharedOutput) {
+        let midi_con = &mut self.0.lock().unwrap();
+        if let Some(port) = midi_con.out_ports.get_mut(&key)
+            && port
+                .connection
+                .as_ref()
+                .is_some_and(|connection| Arc::ptr_eq(connection, output))
+        {
+            trace!("Destination detached: {:?}", key);
+            port.connection = None;
+        }
+    }
+
+    /// Disconnect from a MIDI source by its id
+    pub fn disconnect_source(&self, source: EndpointId) -> Result<(), MidiError> {
+        let midi_con = &mut self.0.lock().unwrap();
+        let key = midi_con
+            .in_port_key(source)
+            .ok_or(MidiError::NotConnected(source))?;
+        let input_port = midi_con.in_ports.remove(&key);
+        drop(input_port);
+        trace!("Disconnected from source: {:?}", source);
+        Ok(())
+    }
+
+    /// Disconnect from a MIDI destination by its id
+    pub fn disconnect_destination(&self, destination: EndpointId) -> Result<(), MidiError> {
+        let midi_con = &mut self.0.lock().unwrap();
+        let key = midi_con
+            .out_port_key(destination)
+            .ok_or(MidiError::NotConnected(destination))?;
+        let output_port = midi_con.out_ports.remove(&key);
+        drop(output_port);
+        if let Some(scheduler) = &midi_con.scheduler {
+            scheduler.flush(key);
+        }
+        trace!("Disconnected from destination: {:?}", destination);
+        Ok(())
+    }
+}
+
+/// Close connections of detached destinations, and reopen re-attached ones.
+fn reconnect_destinations(
+    midi_con: &mut MidiCon,
+    destinations: &[(EndpointId, String)],
+) -> Vec<Notification> {
+    let mut notifications = vec![];
+    let mut connected = midi_con.connected_destinations();
+    for (key, port) in midi_con.out_ports.iter_mut() {
+        if port.connection.is_some() {
+            if !destinations.iter().any(|(id, _)| *id == port.endpoint) {
+                trace!("Destination detached: {:?}", key);
+                port.connection = None;
+            }
+            continue;
+        }
+        let Some((index, (id, name))) =
+            find_endpoint(destinations, port.endpoint, &port.name, |id| {
+                connected.contains(&id)
+            })
+        else {
+            continue;
+        };
+        match midi_con.backend.open_output(*id) {
+            Ok(output) => {
+                trace!("Destination reconnected: {:?} as {:?}", key, id);
+                connected.push(*id);
+                port.endpoint = *id;
+                port.connection = Some(Arc::new(Mutex::new(output)));
+                notifications.push(Notification::DestinationReconnected {
+                    id: *key,
+                    index,
+                    name: name.clone(),
+                });
+            }
+            Err(err) => trace!("Failed to reconnect destination {:?}: {}", key, err),
+        }
+    }
+    notifications
+}
+
+/// Index of the endpoint `id` in `endpoints`, or failing that, of the first
+/// endpoint named `name` which is not `connected` already.
+pub(crate) fn find_endpoint<'a>(
+    endpoints: &'a [(EndpointId, String)],
+    id: EndpointId,
+    name: &str,
+    connected: impl Fn(EndpointId) -> bool,
+) -> Option<(usize, &'a (EndpointId, String))> {
+    let mut enumerated = endpoints.iter().enumerate();
+    enumerated
+        .clone()
+        .find(|(_, (endpoint, _))| *endpoint == id)
+        .or_else(|| {
+            enumerated.find(|(_, (endpoint, endpoint_name))| {
+                endpoint_name == name && !connected(*endpoint)
+            })
+        })
+}
+
+/// Notifications for endpoints removed from `old` and added to `new`, indexed
+/// into the respective list.
+fn diff_endpoints(
+    old: &[(EndpointId, String)],
+    new: &[(EndpointId, String)],
+    removed: impl Fn(EndpointId, usize, String) -> Notification,
+    added: impl Fn(EndpointId, usize, String) -> Notification,
+) -> Vec<Notification> {
+    let mut notifications = vec![];
+    for (index, (id, name)) in old.iter().enumerate() {
+        if !new.iter().any(|(new_id, _)| new_id == id) {
+            notifications.push(removed(*id, index, name.clone()));
+        }
+    }
+    for (index, (id, name)) in new.iter().enumerate() {
+        if !old.iter().any(|(old_id, _)| old_id == id) {
+            notifications.push(added(*id, index, name.clone()));
+        }
+    }
+    notifications
+}
@@ -0,0 +1,569 @@
+//! In-process MIDI backend for tests.
+//!
+//! The [`MockBackend`] has no sources or destinations of its own. Tests add fake
+//! endpoints, inject data into sources, inspect what was sent to destinations and
+//! plug or unplug endpoints to simulate devices being attached or detached.
+//! Virtual endpoints are listed along with the fake ones, so data sent from a
+//! virtual source is received by the inputs connected to it, and data sent to
+//! a virtual destination is passed to its callback.
+//! Time is simulated as well, it only advances when told to, see
+//! [`MockBackend::advance`].
+//!
+//! ```
+//! use rmidi::midi_con::ArcMutexMidiCon;
+//! use rmidi::mock::MockBackend;
+//!
+//! let mock = MockBackend::new();
+//! let keys = mock.add_source("Keys");
+//! let synth = mock.add_destination("Synth");
+//!
+//! let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+//! midi_con.connect_destination(synth.id())?;
+//! let synth_id = synth.id();
+//! midi_con.connect_source(keys.id(), move |_, data, mc| mc.send(synth_id, data).unwrap())?;
+//!
+//! keys.inject(&[0x90, 60, 100]);
+//! assert_eq!(synth.sent(), vec![vec![0x90, 60, 100]]);
+//! # Ok::<(), rmidi::error::MidiError>(())
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::backend::{
+    InputCallback, InputConnection, MidiBackend, NotificationHandler, OutputConnection,
+};
+use crate::error::MidiError;
+use crate::midi_con::{EndpointId, EndpointInfo, Notification};
+
+#[derive(Default)]
+struct MockState {
+    next_id: u64,
+    /// Simulated time since the session epoch
+    now: Duration,
+    sources: Vec<MockEndpoint>,
+    destinations: Vec<MockEndpoint>,
+    /// Open inputs as (connection id, source id, callback)
+    inputs: Vec<(u64, u64, Arc<Mutex<InputCallback>>)>,
+}
+
+struct MockEndpoint {
+    id: u64,
+    name: String,
+    present: bool,
+    /// Data sent, as (delivery time, data)
+    sent: Vec<(Duration, Vec<u8>)>,
+    /// Callback of a virtual destination
+    callback: Option<Arc<Mutex<InputCallback>>>,
+    /// Created as a virtual endpoint
+    is_virtual: bool,
+    /// Device as set by `set_device`, as (manufacturer, model, device name)
+    device: Option<(String, String, String)>,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn present(endpoints: &[MockEndpoint]) -> impl Iterator<Item = &MockEndpoint> {
+        endpoints.iter().filter(|endpoint| endpoint.present)
+    }
+
+    fn info(endpoints: &[MockEndpoint]) -> Vec<EndpointInfo> {
+        Self::present(endpoints)
+            .map(|endpoint| {
+                let mut info = EndpointInfo::new(EndpointId(endpoint.id), endpoint.name.clone());
+                info.is_virtual = endpoint.is_virtual;
+                if let Some((manufacturer, model, device)) = &endpoint.device {
+                    info.display_name = format!("{} {}", device, endpoint.name);
+                    info.manufacturer = Some(manufacturer.clone());
+                    info.model = Some(model.clone());
+                    info.device = Some(device.clone());
+                }
+                info
+            })
+            .collect()
+    }
+}
+
+/// In-process MIDI backend, see the [module](self) documentation.
+///
+/// Clones share the same set of endpoints, so a test can keep a clone for
+/// driving the backend while [`ArcMutexMidiCon`](crate::midi_con::ArcMutexMidiCon)
+/// owns another.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    handler: Arc<Mutex<Option<Arc<NotificationHandler>>>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advance the simulated time by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().now += duration;
+    }
+
+    /// Add a (plugged in) source named `name`.
+    pub fn add_source(&self, name: &str) -> MockSource {
+        let id = self.add_endpoint(name, |state| &mut state.sources);
+        MockSource {
+            backend: self.clone(),
+            id,
+        }
+    }
+
+    /// Add a (plugged in) destination named `name`.
+    pub fn add_destination(&self, name: &str) -> MockDestination {
+        let id = self.add_endpoint(name, |state| &mut state.destinations);
+        MockDestination {
+            backend: self.clone(),
+            id,
+        }
+    }
+
+    fn add_endpoint(
+        &self,
+        name: &str,
+        endpoints: impl Fn(&mut MockState) -> &mut Vec<MockEndpoint>,
+    ) -> u64 {
+        let id = self.insert_endpoint(name, false, None, endpoints);
+        self.notify();
+        id
+    }
+
+    /// Insert an endpoint without notifying, for endpoints created through
+    /// the backend, i.e., while the connection holds its lock.
+    fn insert_endpoint(
+        &self,
+        name: &str,
+        is_virtual: bool,
+        callback: Option<InputCallback>,
+        endpoints: impl Fn(&mut MockState) -> &mut Vec<MockEndpoint>,
+    ) -> u64 {
+        let state = &mut *self.state.lock().unwrap();
+        let id = state.next_id();
+        endpoints(state).push(MockEndpoint {
+            id,
+            name: name.to_string(),
+            present: true,
+            sent: vec![],
+            callback: callback.map(|callback| Arc::new(Mutex::new(callback))),
+            is_virtual,
+            device: None,
+        });
+        id
+    }
+
+    fn remove_endpoint(
+        &self,
+        id: u64,
+        endpoints: impl Fn(&mut MockState) -> &mut Vec<MockEndpoint>,
+    ) {
+        endpoints(&mut self.state.lock().unwrap()).retain(|endpoint| endpoint.id != id);
+        self.notify();
+    }
+
+    /// Deliver `data` to all inputs connected to the source `id`, unless
+    /// unplugged.
+    fn deliver(&self, id: u64, data: &[u8]) {
+        let (now, callbacks): (_, Vec<_>) = {
+            let state = self.state.lock().unwrap();
+            if !MockState::present(&state.sources).any(|source| source.id == id) {
+                return;
+            }
+            let callbacks = state
+                .inputs
+                .iter()
+                .filter(|(_, source_id, _)| *source_id == id)
+                .map(|(_, _, callback)| callback.clone())
+                .collect();
+            (state.now, callbacks)
+        };
+        // callbacks may call back into the backend
+        for callback in callbacks {
+            (callback.lock().unwrap())(now, data);
+        }
+    }
+
+    fn set_present(
+        &self,
+        id: u64,
+        present: bool,
+        endpoints: impl Fn(&mut MockState) -> &mut Vec<MockEndpoint>,
+    ) {
+        let changed = {
+            let state = &mut *self.state.lock().unwrap();
+            match endpoints(state)
+                .iter_mut()
+                .find(|endpoint| endpoint.id == id)
+            {
+                Some(endpoint) if endpoint.present != present => {
+                    endpoint.present = present;
+                    true
+                }
+                _ => false,
+            }
+        };
+        if changed {
+            self.notify();
+        }
+    }
+
+    fn set_device(
+        &self,
+        id: u64,
+        device: (String, String, String),
+        endpoints: impl Fn(&mut MockState) -> &mut Vec<MockEndpoint>,
+    ) {
+        let state = &mut *self.state.lock().unwrap();
+        if let Some(endpoint) = endpoints(state)
+            .iter_mut()
+            .find(|endpoint| endpoint.id == id)
+        {
+            endpoint.device = Some(device);
+        }
+    }
+
+    fn notify(&self) {
+        // the handler may cause further notifications
+        let opt_handler = self.handler.lock().unwrap().clone();
+        if let Some(handler) = opt_handler {
+            handler(&Notification::SetupChanged);
+        }
+    }
+}
+
+impl MidiBackend for MockBackend {
+    fn sources(&self) -> Vec<(EndpointId, String)> {
+        let state = self.state.lock().unwrap();
+        MockState::present(&state.sources)
+            .map(|source| (EndpointId(source.id), source.name.clone()))
+            .collect()
+    }
+
+    fn destinations(&self) -> Vec<(EndpointId, String)> {
+        let state = self.state.lock().unwrap();
+        MockState::present(&state.destinations)
+            .map(|destination| (EndpointId(destination.id), destination.name.clone()))
+            .collect()
+    }
+
+    fn source_info(&self) -> Vec<EndpointInfo> {
+        MockState::info(&self.state.lock().unwrap().sources)
+    }
+
+    fn destination_info(&self) -> Vec<EndpointInfo> {
+        MockState::info(&self.state.lock().unwrap().destinations)
+    }
+
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn set_notification_handler(&mut self, handler: NotificationHandler) {
+        *self.handler.lock().unwrap() = Some(Arc::new(handler));
+    }
+
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError> {
+        let state = &mut *self.state.lock().unwrap();
+        let source_id = MockState::present(&state.sources)
+            .find(|endpoint| endpoint.id == source.0)
+            .ok_or(MidiError::UnknownEndpoint(source))?
+            .id;
+        let id = state.next_id();
+        state
+            .inputs
+            .push((id, source_id, Arc::new(Mutex::new(callback))));
+        Ok(Box::new(MockInput {
+            state: self.state.clone(),
+            id,
+        }))
+    }
+
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError> {
+        let state = self.state.lock().unwrap();
+        let destination_id = MockState::present(&state.destinations)
+            .find(|endpoint| endpoint.id == destination.0)
+            .ok_or(MidiError::UnknownEndpoint(destination))?
+            .id;
+        Ok(Box::new(MockOutput {
+            state: self.state.clone(),
+            destination_id,
+        }))
+    }
+
+    fn create_virtual_source(
+        &mut self,
+        name: &str,
+    ) -> Result<(EndpointId, Box<dyn OutputConnection>), MidiError> {
+        let id = self.insert_endpoint(name, true, None, |state| &mut state.sources);
+        Ok((
+            EndpointId(id),
+            Box::new(MockVirtualSource {
+                backend: self.clone(),
+                id,
+            }),
+        ))
+    }
+
+    fn create_virtual_destination(
+        &mut self,
+        name: &str,
+        callback: InputCallback,
+    ) -> Result<(EndpointId, Box<dyn InputConnection>), MidiError> {
+        let id = self.insert_endpoint(name, true, Some(callback), |state| &mut state.destinations);
+        Ok((
+            EndpointId(id),
+            Box::new(MockVirtualDestination {
+                backend: self.clone(),
+                id,
+            }),
+        ))
+    }
+}
+
+struct MockInput {
+    state: Arc<Mutex<MockState>>,
+    id: u64,
+}
+
+impl InputConnection for MockInput {}
+
+impl Drop for MockInput {
+    fn drop(&mut self) {
+        let state = &mut *self.state.lock().unwrap();
+        state.inputs.retain(|(id, _, _)| *id != self.id);
+    }
+}
+
+struct MockOutput {
+    state: Arc<Mutex<MockState>>,
+    destination_id: u64,
+}
+
+impl MockOutput {
+    fn with_destination<T>(
+        &self,
+        f: impl FnOnce(&mut MockEndpoint, Duration) -> T,
+    ) -> Result<T, MidiError> {
+        let state = &mut *self.state.lock().unwrap();
+        let now = state.now;
+        let destination = state
+            .destinations
+            .iter_mut()
+            .find(|destination| destination.id == self.destination_id && destination.present)
+            .ok_or(MidiError::DeviceGone)?;
+        Ok(f(destination, now))
+    }
+}
+
+/// Data scheduled for later is logged right away, with the time it is due.
+/// Data sent to a virtual destination is passed to its callback as well, at
+/// the time sent.
+impl OutputConnection for MockOutput {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        let (now, opt_callback) = self.with_destination(|destination, now| {
+            destination.sent.push((now, data.to_vec()));
+            (now, destination.callback.clone())
+        })?;
+        // the callback may call back into the backend
+        if let Some(callback) = opt_callback {
+            (callback.lock().unwrap())(now, data);
+        }
+        Ok(())
+    }
+
+    fn schedules(&self) -> bool {
+        true
+    }
+
+    fn send_at(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), MidiError> {
+        self.with_destination(|destination, now| {
+            destination.sent.push((timestamp.max(now), data.to_vec()))
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), MidiError> {
+        self.with_destination(|destination, now| {
+            destination.sent.retain(|(timestamp, _)| *timestamp <= now)
+        })
+    }
+}
+
+/// Handle to a fake source of a [`MockBackend`].
+#[derive(Clone)]
+pub struct MockSource {
+    backend: MockBackend,
+    id: u64,
+}
+
+impl MockSource {
+    /// Id of the source, as listed by the backend.
+    pub fn id(&self) -> EndpointId {
+        EndpointId(self.id)
+    }
+
+    /// Deliver `data` to all inputs connected to the source, as if received
+    /// from a device at the current simulated time. Data injected while
+    /// unplugged is lost.
+    pub fn inject(&self, data: &[u8]) {
+        self.backend.deliver(self.id, data);
+    }
+
+    /// Number of inputs currently connected to the source.
+    pub fn connections(&self) -> usize {
+        let state = self.backend.state.lock().unwrap();
+        state
+            .inputs
+            .iter()
+            .filter(|(_, source_id, _)| *source_id == self.id)
+            .count()
+    }
+
+    /// Simulate the device being detached.
+    pub fn unplug(&self) {
+        self.backend
+            .set_present(self.id, false, |state| &mut state.sources);
+    }
+
+    /// Simulate the device being (re-)attached.
+    pub fn plug(&self) {
+        self.backend
+            .set_present(self.id, true, |state| &mut state.sources);
+    }
+
+    /// Describe the source as a port of `device` by `manufacturer`, see
+    /// [`EndpointInfo`].
+    pub fn set_device(&self, manufacturer: &str, model: &str, device: &str) {
+        let device = (
+            manufacturer.to_string(),
+            model.to_string(),
+            device.to_string(),
+        );
+        self.backend
+            .set_device(self.id, device, |state| &mut state.sources);
+    }
+}
+
+/// Handle to a fake destination of a [`MockBackend`].
+#[derive(Clone)]
+pub struct MockDestination {
+    backend: MockBackend,
+    id: u64,
+}
+
+impl MockDestination {
+    /// Id of the destination, as listed by the backend.
+    pub fn id(&self) -> EndpointId {
+        EndpointId(self.id)
+    }
+
+    /// All data sent to the destination so far, including data scheduled for
+    /// later.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.sent_at().into_iter().map(|(_, data)| data).collect()
+    }
+
+    /// All data sent to the destination so far, clearing the log.
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        self.take_sent_at()
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect()
+    }
+
+    /// All data sent to the destination so far, with the (simulated) time of
+    /// delivery.
+    pub fn sent_at(&self) -> Vec<(Duration, Vec<u8>)> {
+        self.with_endpoint(|destination| destination.sent.clone())
+    }
+
+    /// All data sent to the destination so far, with the (simulated) time of
+    /// delivery, clearing the log.
+    pub fn take_sent_at(&self) -> Vec<(Duration, Vec<u8>)> {
+        self.with_endpoint(|destination| std::mem::take(&mut destination.sent))
+    }
+
+    /// Simulate the device being detached, sending to it fails with
+    /// [`MidiError::DeviceGone`] until plugged again.
+    pub fn unplug(&self) {
+        self.backend
+            .set_present(self.id, false, |state| &mut state.destinations);
+    }
+
+    /// Simulate the device being (re-)attached.
+    pub fn plug(&self) {
+        self.backend
+            .set_present(self.id, true, |state| &mut state.destinations);
+    }
+
+    /// Describe the destination as a port of `device` by `manufacturer`, see
+    /// [`EndpointInfo`].
+    pub fn set_device(&self, manufacturer: &str, model: &str, device: &str) {
+        let device = (
+            manufacturer.to_string(),
+            model.to_string(),
+            device.to_string(),
+        );
+        self.backend
+            .set_device(self.id, device, |state| &mut state.destinations);
+    }
+
+    fn with_endpoint<T>(&self, f: impl FnOnce(&mut MockEndpoint) -> T) -> T {
+        let state = &mut *self.backend.state.lock().unwrap();
+        let destination = state
+            .destinations
+            .iter_mut()
+            .find(|destination| destination.id == self.id)
+            .unwrap();
+        f(destination)
+    }
+}
+
+/// Virtual source, delivering data sent to the inputs connected to it.
+struct MockVirtualSource {
+    backend: MockBackend,
+    id: u64,
+}
+
+impl OutputConnection for MockVirtualSource {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.backend.deliver(self.id, data);
+        Ok(())
+    }
+}
+
+impl Drop for MockVirtualSource {
+    fn drop(&mut self) {
+        self.backend
+            .remove_endpoint(self.id, |state| &mut state.sources);
+    }
+}
+
+/// Virtual destination, its callback is kept with the endpoint.
+struct MockVirtualDestination {
+    backend: MockBackend,
+    id: u64,
+}
+
+impl InputConnection for MockVirtualDestination {}
+
+impl Drop for MockVirtualDestination {
+    fn drop(&mut self) {
+        self.backend
+            .remove_endpoint(self.id, |state| &mut state.destinations);
+    }
+}
@@ -0,0 +1,426 @@
+//! Playback of Standard MIDI Files to a destination.
+//!
+//! A [`Player`] converts the ticks of a file to time through its tempo map,
+//! and schedules the events due within a lookahead window by
+//! [`ArcMutexMidiCon::send_at`], so events are delivered on time regardless
+//! of how often [`Player::update`] is called to schedule the following ones.
+//!
+//! ```no_run
+//! use rmidi::midi_con::ArcMutexMidiCon;
+//! use rmidi::player::Player;
+//! use rmidi::smf::Smf;
+//!
+//! let midi_con = ArcMutexMidiCon::new()?;
+//! let destination = midi_con.connect_destination_by_index(0)?;
+//! let smf = Smf::parse(&std::fs::read("song.mid")?)?;
+//! let mut player = Player::new(&midi_con, destination, &smf)?;
+//! player.play()?;
+//! while player.update()? {
+//!     std::thread::sleep(std::time::Duration::from_millis(20));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::MidiError;
+use crate::midi_con::{ArcMutexMidiCon, EndpointId};
+use crate::smf::{Event, Format, MetaEvent, Smf, Timing};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::time::Duration;
+
+/// Player of a Standard MIDI File, see the [module](self) documentation.
+///
+/// Positions are times within the file at its own tempo, regardless of the
+/// tempo scale.
+///
+/// The player must own its destination exclusively: pausing, stopping,
+/// seeking, looping, scaling the tempo and muting drop the data scheduled by
+/// [`ArcMutexMidiCon::flush`], which includes data scheduled for the
+/// destination by others, e.g., the ticks of a
+/// [`ClockMaster`](crate::clock::ClockMaster).
+pub struct Player {
+    midi_con: ArcMutexMidiCon,
+    destination: EndpointId,
+    /// Events to send, ordered by time
+    events: Vec<PlayerEvent>,
+    /// Time of the end of the last track
+    duration: Duration,
+    muted: Vec<bool>,
+    tempo_scale: f64,
+    opt_loop: Option<Range<Duration>>,
+    lookahead: Duration,
+    /// Position while not playing
+    position: Duration,
+    opt_anchor: Option<Anchor>,
+    /// Index of the next event to schedule
+    next: usize,
+    /// Notes scheduled to be on, as (channel, note), with the timestamp of
+    /// their note off if scheduled
+    notes: BTreeMap<(u8, u8), Option<Duration>>,
+    /// Channels of the channel messages scheduled since the last silence
+    channels: BTreeSet<u8>,
+}
+
+struct PlayerEvent {
+    time: Duration,
+    track: usize,
+    data: Vec<u8>,
+}
+
+/// Timestamp at which the position `origin` is played.
+#[derive(Clone, Copy)]
+struct Anchor {
+    timestamp: Duration,
+    origin: Duration,
+}
+
+impl Player {
+    /// Player of `smf`, sending to the connected `destination`.
+    ///
+    /// Format 2 files, of independent sequences, are not supported.
+    pub fn new(
+        midi_con: &ArcMutexMidiCon,
+        destination: EndpointId,
+        smf: &Smf,
+    ) -> Result<Self, MidiError> {
+        if smf.format == Format::Sequential {
+            return Err(MidiError::Unsupported("playing format 2"));
+        }
+        let tempo_map = TempoMap::new(smf);
+        let mut events = vec![];
+        let mut end = 0;
+        for (track, events_of_track) in smf.tracks.iter().enumerate() {
+            let mut tick = 0;
+            for event in &events_of_track.events {
+                tick += event.delta as u64;
+                // empty escape events have nothing to send
+                if let Some(data) = event.event.midi_data().filter(|data| !data.is_empty()) {
+                    events.push(PlayerEvent {
+                        time: tempo_map.time(tick),
+                        track,
+                        data,
+                    });
+                }
+            }
+            end = end.max(tick);
+        }
+        // stable, keeping the order of tracks for events at the same time
+        events.sort_by_key(|event| event.time);
+        Ok(Player {
+            midi_con: midi_con.clone(),
+            destination,
+            events,
+            duration: tempo_map.time(end),
+            muted: vec![false; smf.tracks.len()],
+            tempo_scale: 1.0,
+            opt_loop: None,
+            lookahead: Duration::from_millis(100),
+            position: Duration::ZERO,
+            opt_anchor: None,
+            next: 0,
+            notes: BTreeMap::new(),
+            channels: BTreeSet::new(),
+        })
+    }
+
+    /// Time of the end of the file.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Current position.
+    pub fn position(&self) -> Duration {
+        match self.opt_anchor {
+            Some(anchor) => self.position_at(anchor, self.midi_con.now()),
+            None => self.position,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.opt_anchor.is_some()
+    }
+
+    /// Set how far ahead of time events are scheduled, 100 ms by default.
+    /// [`update`](Self::update) must be called at least as often.
+    pub fn set_lookahead(&mut self, lookahead: Duration) {
+        self.lookahead = lookahead;
+    }
+
+    /// Start or resume playing from the current position.
+    pub fn play(&mut self) -> Result<(), MidiError> {
+        if self.opt_anchor.is_none() {
+            self.start(self.position)?;
+        }
+        Ok(())
+    }
+
+    /// Stop playing, keeping the position, silencing sounding notes.
+    pub fn pause(&mut self) -> Result<(), MidiError> {
+        if self.opt_anchor.is_some() {
+            self.position = self.position();
+            self.opt_anchor = None;
+            self.silence()?;
+        }
+        Ok(())
+    }
+
+    /// Stop playing and rewind, silencing sounding notes.
+    pub fn stop(&mut self) -> Result<(), MidiError> {
+        self.pause()?;
+        self.position = Duration::ZERO;
+        Ok(())
+    }
+
+    /// Continue at `position`, silencing sounding notes if playing.
+    pub fn seek(&mut self, position: Duration) -> Result<(), MidiError> {
+        let position = position.min(self.duration);
+        if self.opt_anchor.is_some() {
+            self.silence()?;
+            self.start(position)?;
+        } else {
+            self.position = position;
+        }
+        Ok(())
+    }
+
+    /// Repeat `range` of positions once played into, or play on for `None`.
+    pub fn set_loop(&mut self, opt_loop: Option<Range<Duration>>) -> Result<(), MidiError> {
+        self.opt_loop = opt_loop;
+        self.reschedule()
+    }
+
+    /// Play at `scale` times the tempo of the file, e.g., 0.5 for half the
+    /// tempo.
+    pub fn set_tempo_scale(&mut self, scale: f64) -> Result<(), MidiError> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(MidiError::InvalidSetting {
+                field: "tempo scale",
+                value: scale.to_string(),
+            });
+        }
+        let position = self.position();
+        self.tempo_scale = scale;
+        if self.opt_anchor.is_some() {
+            self.midi_con.flush(self.destination)?;
+            self.start(position)?;
+        }
+        Ok(())
+    }
+
+    /// Mute or unmute the track at `index` of the file. Note offs of muted
+    /// tracks are still sent, so notes sounding when muted are released.
+    pub fn set_muted(&mut self, index: usize, muted: bool) -> Result<(), MidiError> {
+        let track = self.muted.get_mut(index).ok_or(MidiError::InvalidSetting {
+            field: "track",
+            value: index.to_string(),
+        })?;
+        *track = muted;
+        self.reschedule()
+    }
+
+    /// Schedule the events due within the lookahead window, returning whether
+    /// still playing.
+    pub fn update(&mut self) -> Result<bool, MidiError> {
+        let now = self.midi_con.now();
+        let horizon = now + self.lookahead;
+        while let Some(anchor) = self.opt_anchor {
+            // loop if played into the loop range
+            let opt_loop_end = self
+                .opt_loop
+                .clone()
+                .filter(|range| range.start < range.end && anchor.origin < range.end)
+                .map(|range| range.end);
+            let end = opt_loop_end.unwrap_or(self.duration);
+            while let Some(event) = self.events.get(self.next) {
+                let timestamp = self.timestamp(anchor, event.time);
+                if event.time >= end && opt_loop_end.is_some() || timestamp > horizon {
+                    break;
+                }
+                let (track, data) = (event.track, event.data.clone());
+                self.next += 1;
+                self.schedule(track, timestamp, &data)?;
+            }
+            let end_timestamp = self.timestamp(anchor, end);
+            let done = self
+                .events
+                .get(self.next)
+                .is_none_or(|event| event.time >= end);
+            match opt_loop_end {
+                Some(_) if done && end_timestamp <= horizon => {
+                    // release notes at the end of the loop, continue at its start
+                    for data in self.silence_data(end_timestamp) {
+                        self.midi_con
+                            .send_at(self.destination, end_timestamp, &data)?;
+                    }
+                    let start = self.opt_loop.as_ref().unwrap().start;
+                    self.opt_anchor = Some(Anchor {
+                        timestamp: end_timestamp,
+                        origin: start,
+                    });
+                    self.next = self.index(start);
+                }
+                None if done && end_timestamp <= now => {
+                    self.opt_anchor = None;
+                    self.position = self.duration;
+                }
+                _ => break,
+            }
+        }
+        Ok(self.opt_anchor.is_some())
+    }
+
+    /// Start playing at `position`.
+    fn start(&mut self, position: Duration) -> Result<(), MidiError> {
+        self.opt_anchor = Some(Anchor {
+            timestamp: self.midi_con.now(),
+            origin: position,
+        });
+        self.next = self.index(position);
+        self.update()?;
+        Ok(())
+    }
+
+    /// Drop the events scheduled and schedule them again from the current
+    /// position, e.g., after a change of muted tracks.
+    fn reschedule(&mut self) -> Result<(), MidiError> {
+        if let Some(anchor) = self.opt_anchor {
+            self.midi_con.flush(self.destination)?;
+            let now = self.midi_con.now();
+            let position = self.position_at(anchor, now);
+            self.opt_anchor = Some(Anchor {
+                timestamp: now,
+                origin: position,
+            });
+            // events at the current position have been sent
+            self.next = self.events.partition_point(|event| event.time <= position);
+            self.update()?;
+        }
+        Ok(())
+    }
+
+    /// Drop the events scheduled and release the sounding notes right away.
+    fn silence(&mut self) -> Result<(), MidiError> {
+        self.midi_con.flush(self.destination)?;
+        for data in self.silence_data(self.midi_con.now()) {
+            self.midi_con.send(self.destination, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Note offs of the notes on at `timestamp`, i.e., without a note off
+    /// delivered by then, followed by sustain off and all notes off on all
+    /// channels used, forgetting about both.
+    fn silence_data(&mut self, timestamp: Duration) -> Vec<Vec<u8>> {
+        let notes = std::mem::take(&mut self.notes);
+        let channels = std::mem::take(&mut self.channels);
+        let note_offs = notes
+            .into_iter()
+            .filter(|(_, opt_off)| opt_off.is_none_or(|off| off > timestamp))
+            .map(|((channel, note), _)| vec![0x80 | channel, note, 0]);
+        let all_notes_off = channels
+            .into_iter()
+            .flat_map(|channel| [vec![0xb0 | channel, 64, 0], vec![0xb0 | channel, 123, 0]]);
+        note_offs.chain(all_notes_off).collect()
+    }
+
+    /// Schedule the `data` of an event of `track`, unless muted.
+    fn schedule(
+        &mut self,
+        track: usize,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> Result<(), MidiError> {
+        let status = data[0];
+        let note_off = matches!(data, [0x80..=0x8f, ..] | [0x90..=0x9f, _, 0]);
+        if self.muted[track] && !note_off {
+            return Ok(());
+        }
+        if (0x80..0xf0).contains(&status) {
+            let channel = status & 0x0f;
+            self.channels.insert(channel);
+            if note_off {
+                self.notes.insert((channel, data[1]), Some(timestamp));
+            } else if status & 0xf0 == 0x90 {
+                self.notes.insert((channel, data[1]), None);
+            }
+        }
+        self.midi_con.send_at(self.destination, timestamp, data)
+    }
+
+    /// Index of the first event at or after `position`.
+    fn index(&self, position: Duration) -> usize {
+        self.events.partition_point(|event| event.time < position)
+    }
+
+    /// Timestamp at which `position` is played.
+    fn timestamp(&self, anchor: Anchor, position: Duration) -> Duration {
+        let elapsed = position.saturating_sub(anchor.origin);
+        anchor.timestamp + elapsed.div_f64(self.tempo_scale)
+    }
+
+    /// Position played at `timestamp`.
+    fn position_at(&self, anchor: Anchor, timestamp: Duration) -> Duration {
+        let elapsed = timestamp.saturating_sub(anchor.timestamp);
+        (anchor.origin + elapsed.mul_f64(self.tempo_scale)).min(self.duration)
+    }
+}
+
+/// Conversion of ticks to time, by the tempo changes of a file.
+struct TempoMap {
+    timing: Timing,
+    /// Tempo changes as (tick, time at the tick, microseconds per quarter)
+    changes: Vec<(u64, Duration, u32)>,
+}
+
+impl TempoMap {
+    fn new(smf: &Smf) -> Self {
+        let mut tempos = vec![];
+        for track in &smf.tracks {
+            let mut tick = 0;
+            for event in &track.events {
+                tick += event.delta as u64;
+                if let Event::Meta(MetaEvent::Tempo(tempo)) = event.event {
+                    tempos.push((tick, tempo));
+                }
+            }
+        }
+        tempos.sort_by_key(|(tick, _)| *tick);
+
+        let mut map = TempoMap {
+            timing: smf.timing,
+            // 120 bpm until the first tempo change
+            changes: vec![(0, Duration::ZERO, 500_000)],
+        };
+        for (tick, tempo) in tempos {
+            let time = map.time(tick);
+            map.changes.push((tick, time, tempo));
+        }
+        map
+    }
+
+    /// Time at `tick`.
+    fn time(&self, tick: u64) -> Duration {
+        let nanos = match self.timing {
+            Timing::Metrical(ticks_per_quarter) => {
+                let index = self
+                    .changes
+                    .partition_point(|(change_tick, _, _)| *change_tick <= tick);
+                let (change_tick, time, tempo) = self.changes[index - 1];
+                let nanos = (tick - change_tick) as u128 * tempo as u128 * 1000
+                    / ticks_per_quarter.max(1) as u128;
+                return time + Duration::from_nanos(nanos as u64);
+            }
+            // 29 being 30 drop frame, i.e., 29.97 frames per second
+            Timing::Timecode {
+                fps: 29,
+                ticks_per_frame,
+            } => tick as u128 * 1_001_000_000_000 / (30_000 * ticks_per_frame.max(1) as u128),
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } => tick as u128 * 1_000_000_000 / (fps as u128 * ticks_per_frame.max(1) as u128),
+        };
+        Duration::from_nanos(nanos as u64)
+    }
+}
@@ -0,0 +1,257 @@
+//! Recording of incoming data into a Standard MIDI File.
+//!
+//! An [`SmfRecorder`] converts the timestamps of incoming data to ticks at a
+//! fixed tempo, from the time the recording started. Sources are recorded by
+//! connecting them with [`ArcMutexMidiCon::connect_source_recorder`] and its
+//! variants, or by passing incoming data to [`SmfRecorder::record`].
+//!
+//! ```no_run
+//! use rmidi::midi_con::ArcMutexMidiCon;
+//! use rmidi::recorder::SmfRecorder;
+//! use rmidi::smf::Format;
+//!
+//! let midi_con = ArcMutexMidiCon::new()?;
+//! // 480 ticks per quarter note at 120 bpm
+//! let recorder = SmfRecorder::new(Format::Simultaneous, 480, 500_000, midi_con.now())?;
+//! let source = midi_con.connect_source_by_index_recorder(0, &recorder)?;
+//! std::thread::sleep(std::time::Duration::from_secs(10));
+//! midi_con.disconnect_source(source)?;
+//! std::fs::write("recording.mid", recorder.stop().to_bytes()?)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::MidiError;
+use crate::message::{MidiMessage, MidiParser};
+use crate::midi_con::{ArcMutexMidiCon, EndpointId};
+use crate::smf::{Event, Format, MetaEvent, Smf, Timing, Track, TrackEvent};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Recorder of incoming data, see the [module](self) documentation.
+///
+/// System realtime messages, e.g., 24 clock ticks per quarter note, are left
+/// out unless [`set_record_realtime`](Self::set_record_realtime). Clones share
+/// the same recording, e.g., one per connected source.
+#[derive(Clone)]
+pub struct SmfRecorder(Arc<Mutex<RecorderState>>);
+
+struct RecorderState {
+    format: Format,
+    ticks_per_quarter: u16,
+    /// Microseconds per quarter note
+    tempo: u32,
+    /// Timestamp of tick 0
+    start: Duration,
+    /// Whether system realtime messages, e.g., clock, are recorded
+    realtime: bool,
+    stopped: bool,
+    /// Recorded events by source, in order of their first event
+    sources: Vec<SourceRecording>,
+}
+
+struct SourceRecording {
+    source: EndpointId,
+    parser: MidiParser,
+    /// Events by their absolute time in ticks
+    events: Vec<(u32, Event)>,
+}
+
+impl SmfRecorder {
+    /// Recorder of a file of `format`, with `ticks_per_quarter` note at
+    /// `tempo` microseconds per quarter note, from `start` on, e.g.,
+    /// [`ArcMutexMidiCon::now`].
+    ///
+    /// Format 0 merges all sources into a single track, format 1 records
+    /// each source into a track of its own, following a track holding the
+    /// tempo. Format 2 is not supported.
+    pub fn new(
+        format: Format,
+        ticks_per_quarter: u16,
+        tempo: u32,
+        start: Duration,
+    ) -> Result<Self, MidiError> {
+        if format == Format::Sequential {
+            return Err(MidiError::Unsupported("recording format 2"));
+        }
+        if !(1..=0x7fff).contains(&ticks_per_quarter) {
+            return Err(MidiError::InvalidSetting {
+                field: "ticks per quarter note",
+                value: ticks_per_quarter.to_string(),
+            });
+        }
+        if !(1..=0xff_ffff).contains(&tempo) {
+            return Err(MidiError::InvalidSetting {
+                field: "tempo",
+                value: tempo.to_string(),
+            });
+        }
+        Ok(SmfRecorder(Arc::new(Mutex::new(RecorderState {
+            format,
+            ticks_per_quarter,
+            tempo,
+            start,
+            realtime: false,
+            stopped: false,
+            sources: vec![],
+        }))))
+    }
+
+    /// Record system realtime messages as well, e.g., clock and active
+    /// sensing, which are left out by default.
+    pub fn set_record_realtime(&self, realtime: bool) {
+        self.0.lock().unwrap().realtime = realtime;
+    }
+
+    /// Record incoming data of `source`, received at `timestamp`.
+    ///
+    /// SysEx messages are expected as a whole, as delivered by
+    /// [`ArcMutexMidiCon`]. Data recorded after [`stop`](Self::stop) is
+    /// ignored.
+    pub fn record(&self, source: EndpointId, timestamp: Duration, data: &[u8]) {
+        let state = &mut *self.0.lock().unwrap();
+        if state.stopped {
+            return;
+        }
+        let tick = state.tick(timestamp);
+        let index = match state
+            .sources
+            .iter()
+            .position(|recording| recording.source == source)
+        {
+            Some(index) => index,
+            None => {
+                state.sources.push(SourceRecording {
+                    source,
+                    parser: MidiParser::new(),
+                    events: vec![],
+                });
+                state.sources.len() - 1
+            }
+        };
+        let realtime = state.realtime;
+        let recording = &mut state.sources[index];
+        let events = &mut recording.events;
+        recording.parser.parse(data, |message| {
+            if message.is_realtime() && !realtime {
+                return;
+            }
+            let event = match message {
+                MidiMessage::SysEx(mut data) => {
+                    data.push(0xf7);
+                    Event::SysEx(data)
+                }
+                message if message.channel_number().is_some() => Event::Midi(message),
+                // system common and realtime messages are escaped
+                message => match message.to_bytes() {
+                    Ok(data) => Event::Escape(data),
+                    Err(_) => return,
+                },
+            };
+            events.push((tick, event));
+        });
+    }
+
+    /// Stop recording, returning the file recorded so far.
+    pub fn stop(&self) -> Smf {
+        let state = &mut *self.0.lock().unwrap();
+        state.stopped = true;
+        let tempo = (0, Event::Meta(MetaEvent::Tempo(state.tempo)));
+        let tracks = match state.format {
+            Format::SingleTrack => {
+                let mut events: Vec<_> = std::iter::once(tempo)
+                    .chain(
+                        state
+                            .sources
+                            .iter()
+                            .flat_map(|recording| recording.events.iter().cloned()),
+                    )
+                    .collect();
+                // stable, keeping the order of events of the same tick
+                events.sort_by_key(|(tick, _)| *tick);
+                vec![track(events)]
+            }
+            _ => std::iter::once(track(vec![tempo]))
+                .chain(
+                    state
+                        .sources
+                        .iter()
+                        .map(|recording| track(recording.events.clone())),
+                )
+                .collect(),
+        };
+        Smf {
+            format: state.format,
+            timing: Timing::Metrical(state.ticks_per_quarter),
+            tracks,
+        }
+    }
+}
+
+impl RecorderState {
+    /// Ticks since the start of the recording at `timestamp`, rounded to the
+    /// nearest tick, up to the largest delta time of a file.
+    fn tick(&self, timestamp: Duration) -> u32 {
+        let micros = timestamp.saturating_sub(self.start).as_micros();
+        let tempo = self.tempo as u128;
+        let ticks = (micros * self.ticks_per_quarter as u128 + tempo / 2) / tempo;
+        ticks.min(0x0fff_ffff) as u32
+    }
+}
+
+/// Track of events at absolute times in ticks, ending with end of track.
+fn track(events: Vec<(u32, Event)>) -> Track {
+    let mut last = 0;
+    let mut events: Vec<_> = events
+        .into_iter()
+        .map(|(tick, event)| {
+            let delta = tick.saturating_sub(last);
+            last = last.max(tick);
+            TrackEvent { delta, event }
+        })
+        .collect();
+    events.push(TrackEvent {
+        delta: 0,
+        event: Event::Meta(MetaEvent::EndOfTrack),
+    });
+    Track { events }
+}
+
+impl ArcMutexMidiCon {
+    /// Connect to a MIDI source by its id, recording incoming data to
+    /// `recorder`
+    pub fn connect_source_recorder(
+        &self,
+        source: EndpointId,
+        recorder: &SmfRecorder,
+    ) -> Result<(), MidiError> {
+        let recorder = recorder.clone();
+        self.connect_source(source, move |timestamp, data, _| {
+            recorder.record(source, timestamp, data)
+        })
+    }
+
+    /// Connect to a MIDI source by its index, recording incoming data to
+    /// `recorder`
+    pub fn connect_source_by_index_recorder(
+        &self,
+        source_index: usize,
+        recorder: &SmfRecorder,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_index(source_index)?;
+        self.connect_source_recorder(source, recorder)?;
+        Ok(source)
+    }
+
+    /// Connect to a MIDI source by its name, recording incoming data to
+    /// `recorder`
+    pub fn connect_source_by_name_recorder(
+        &self,
+        source_name: &str,
+        recorder: &SmfRecorder,
+    ) -> Result<EndpointId, MidiError> {
+        let source = self.source_by_name(source_name)?;
+        self.connect_source_recorder(source, recorder)?;
+        Ok(source)
+    }
+}
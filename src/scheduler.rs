@@ -0,0 +1,127 @@
+//! Software scheduler for output connections without native scheduling.
+//!
+//! Scheduled data is kept ordered by timestamp and sent through
+//! [`ArcMutexMidiCon::send`] by a dedicated thread once due, so detached
+//! destinations are handled like for data sent right away.
+
+use log::{trace, warn};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::midi_con::{ArcMutexMidiCon, EndpointId, MidiCon};
+
+/// Data scheduled for a destination, ordered by timestamp, and for equal
+/// timestamps, by the order of scheduling.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Scheduled {
+    timestamp: Duration,
+    seq: u64,
+    destination: EndpointId,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: BinaryHeap<Reverse<Scheduled>>,
+    next_seq: u64,
+    stopped: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+/// Handle to the scheduler thread, which stops once the handle is dropped.
+pub(crate) struct Scheduler {
+    shared: Arc<Shared>,
+}
+
+impl Scheduler {
+    /// Start the scheduler for `midi_con`, timestamps being relative to `epoch`.
+    ///
+    /// Only a weak reference is kept, so the scheduler does not keep the
+    /// connection alive.
+    pub(crate) fn new(midi_con: Weak<Mutex<MidiCon>>, epoch: Instant) -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        thread::spawn(move || scheduler_thread(thread_shared, midi_con, epoch));
+        Scheduler { shared }
+    }
+
+    /// Schedule `data` to be sent to `destination` at `timestamp`.
+    pub(crate) fn schedule(&self, destination: EndpointId, timestamp: Duration, data: &[u8]) {
+        let state = &mut *self.shared.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.queue.push(Reverse(Scheduled {
+            timestamp,
+            seq,
+            destination,
+            data: data.to_vec(),
+        }));
+        self.shared.wake.notify_one();
+    }
+
+    /// Drop all data scheduled for `destination`.
+    ///
+    /// This is best-effort for data already due: the thread sends it without
+    /// holding the state, so once taken from the queue, it is sent regardless.
+    pub(crate) fn flush(&self, destination: EndpointId) {
+        let state = &mut *self.shared.state.lock().unwrap();
+        state
+            .queue
+            .retain(|Reverse(scheduled)| scheduled.destination != destination);
+        self.shared.wake.notify_one();
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stopped = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+/// Send scheduled data once due, until the scheduler is dropped.
+fn scheduler_thread(shared: Arc<Shared>, midi_con: Weak<Mutex<MidiCon>>, epoch: Instant) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.stopped {
+            return;
+        }
+        let Some(timestamp) = state.queue.peek().map(|Reverse(next)| next.timestamp) else {
+            state = shared.wake.wait(state).unwrap();
+            continue;
+        };
+        let now = epoch.elapsed();
+        if timestamp > now {
+            state = shared.wake.wait_timeout(state, timestamp - now).unwrap().0;
+            continue;
+        }
+        let Reverse(scheduled) = state.queue.pop().unwrap();
+
+        // send without holding the state, as scheduling holds the connection lock
+        drop(state);
+        let Some(midi_con) = midi_con.upgrade() else {
+            return;
+        };
+        trace!(
+            "Sending data scheduled at {:?}, {:?} late",
+            scheduled.timestamp,
+            now - scheduled.timestamp
+        );
+        if let Err(err) = ArcMutexMidiCon(midi_con).send(scheduled.destination, &scheduled.data) {
+            warn!(
+                "Failed to send scheduled data to {:?}: {}",
+                scheduled.destination, err
+            );
+        }
+        state = shared.state.lock().unwrap();
+    }
+}
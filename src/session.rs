@@ -0,0 +1,140 @@
+//! Snapshot of the connected sources and destinations, to be stored and
+//! restored in a later session.
+//!
+//! [`SessionConfig`] implements `Serialize` and `Deserialize`, so it can be
+//! stored in any format supported by serde, e.g., JSON or TOML.
+
+use crate::error::MidiError;
+use crate::midi_con::{ArcMutexMidiCon, EndpointId, SourceCallback, find_endpoint};
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+
+/// A connected source or destination, by its id and name.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointConfig {
+    pub id: EndpointId,
+    pub name: String,
+}
+
+/// The connected sources and destinations of a session.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionConfig {
+    pub sources: Vec<EndpointConfig>,
+    pub destinations: Vec<EndpointConfig>,
+}
+
+/// Outcome of [`ArcMutexMidiCon::restore`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+    /// Sources neither found by id nor by name
+    pub missing_sources: Vec<EndpointConfig>,
+    /// Destinations neither found by id nor by name
+    pub missing_destinations: Vec<EndpointConfig>,
+    /// Sources found but failed to connect
+    pub failed_sources: Vec<(EndpointConfig, MidiError)>,
+    /// Destinations found but failed to connect
+    pub failed_destinations: Vec<(EndpointConfig, MidiError)>,
+}
+
+impl RestoreReport {
+    /// Whether all sources and destinations were connected.
+    pub fn is_complete(&self) -> bool {
+        self.missing_sources.is_empty()
+            && self.missing_destinations.is_empty()
+            && self.failed_sources.is_empty()
+            && self.failed_destinations.is_empty()
+    }
+}
+
+impl ArcMutexMidiCon {
+    /// Snapshot of the connected sources and destinations, ordered by id.
+    ///
+    /// Detached endpoints are included, as they are still connected once
+    /// re-attached.
+    pub fn snapshot(&self) -> SessionConfig {
+        let midi_con = &self.0.lock().unwrap();
+        let mut sources: Vec<_> = midi_con
+            .in_ports
+            .values()
+            .map(|port| EndpointConfig {
+                id: port.endpoint,
+                name: port.name.clone(),
+            })
+            .collect();
+        let mut destinations: Vec<_> = midi_con
+            .out_ports
+            .values()
+            .map(|port| EndpointConfig {
+                id: port.endpoint,
+                name: port.name.clone(),
+            })
+            .collect();
+        sources.sort_by_key(|source| source.id);
+        destinations.sort_by_key(|destination| destination.id);
+        SessionConfig {
+            sources,
+            destinations,
+        }
+    }
+
+    /// Connect the sources and destinations of `config`, matching each by its
+    /// id, or failing that, by its name.
+    ///
+    /// `callback_factory` provides the callback for each source. Endpoints
+    /// already connected are left as they are. Endpoints not present, or failing
+    /// to connect, are reported in the returned [`RestoreReport`].
+    pub fn restore(
+        &self,
+        config: &SessionConfig,
+        mut callback_factory: impl FnMut(&EndpointConfig) -> SourceCallback,
+    ) -> RestoreReport {
+        let mut report = RestoreReport::default();
+        let (sources, destinations) = {
+            let midi_con = &self.0.lock().unwrap();
+            (midi_con.backend.sources(), midi_con.backend.destinations())
+        };
+
+        for source in &config.sources {
+            let connected = self.0.lock().unwrap().connected_sources();
+            let Some((_, (id, _))) = find_endpoint(&sources, source.id, &source.name, |id| {
+                connected.contains(&id)
+            }) else {
+                trace!("Source missing: {:?}", source);
+                report.missing_sources.push(source.clone());
+                continue;
+            };
+            match self.connect_source(*id, callback_factory(source)) {
+                Ok(()) | Err(MidiError::AlreadyConnected(_)) => {
+                    trace!("Source restored: {:?} as {:?}", source, id)
+                }
+                Err(err) => {
+                    warn!("Failed to restore source {:?}: {}", source, err);
+                    report.failed_sources.push((source.clone(), err));
+                }
+            }
+        }
+
+        for destination in &config.destinations {
+            let connected = self.0.lock().unwrap().connected_destinations();
+            let Some((_, (id, _))) =
+                find_endpoint(&destinations, destination.id, &destination.name, |id| {
+                    connected.contains(&id)
+                })
+            else {
+                trace!("Destination missing: {:?}", destination);
+                report.missing_destinations.push(destination.clone());
+                continue;
+            };
+            match self.connect_destination(*id) {
+                Ok(()) | Err(MidiError::AlreadyConnected(_)) => {
+                    trace!("Destination restored: {:?} as {:?}", destination, id)
+                }
+                Err(err) => {
+                    warn!("Failed to restore destination {:?}: {}", destination, err);
+                    report.failed_destinations.push((destination.clone(), err));
+                }
+            }
+        }
+        report
+    }
+}
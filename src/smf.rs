@@ -0,0 +1,557 @@
+//! Standard MIDI Files (`.mid`), parsed into a typed in-memory representation
+//! and written back.
+//!
+//! Format 0 (a single track), 1 (simultaneous tracks) and 2 (independent
+//! sequences) files are supported. Events keep their delta times in ticks,
+//! the meaning of a tick being given by the [`Timing`] of the header.
+//!
+//! ```
+//! use rmidi::message::MidiMessage;
+//! use rmidi::smf::{Event, Format, Smf, Timing};
+//!
+//! let data = [
+//!     b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+//!     b'M', b'T', b'r', b'k', 0, 0, 0, 11,
+//!     0x00, 0x90, 60, 100, // note on
+//!     0x60, 60, 0,         // a quarter note later, running status
+//!     0x00, 0xff, 0x2f, 0, // end of track
+//! ];
+//! let smf = Smf::parse(&data)?;
+//! assert_eq!(smf.format, Format::SingleTrack);
+//! assert_eq!(smf.timing, Timing::Metrical(96));
+//! assert_eq!(smf.tracks[0].events[1].delta, 96);
+//! assert_eq!(
+//!     smf.tracks[0].events[1].event,
+//!     Event::Midi(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 0 })
+//! );
+//! # Ok::<(), rmidi::error::MidiError>(())
+//! ```
+
+use crate::error::MidiError;
+use crate::message::{self, MidiEncoder, MidiMessage};
+
+/// Contents of a Standard MIDI File.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Smf {
+    pub format: Format,
+    pub timing: Timing,
+    pub tracks: Vec<Track>,
+}
+
+/// Organization of the tracks of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Format 0, a single track.
+    SingleTrack,
+    /// Format 1, tracks played simultaneously, the first one holding the
+    /// tempo map.
+    Simultaneous,
+    /// Format 2, independent single track patterns.
+    Sequential,
+}
+
+/// Meaning of the ticks of delta times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Ticks per quarter note, the duration of a quarter note being given by
+    /// tempo meta events.
+    Metrical(u16),
+    /// Ticks per frame of SMPTE time code at 24, 25, 29 (30 drop frame) or 30
+    /// frames per second.
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+/// A track chunk, ending with [`MetaEvent::EndOfTrack`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Track {
+    pub events: Vec<TrackEvent>,
+}
+
+/// An event of a track, `delta` ticks after the previous one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackEvent {
+    pub delta: u32,
+    pub event: Event,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Channel message.
+    Midi(MidiMessage),
+    /// SysEx event, the data following `0xf0`, up to and including `0xf7`
+    /// unless continued by [`Event::Escape`] events.
+    SysEx(Vec<u8>),
+    /// Escape event, raw data to be sent as is, e.g., the continuation of a
+    /// SysEx message or a realtime message.
+    Escape(Vec<u8>),
+    Meta(MetaEvent),
+}
+
+impl Event {
+    /// The raw MIDI data to send for the event, `None` for meta events.
+    pub fn midi_data(&self) -> Option<Vec<u8>> {
+        match self {
+            // channel messages are checked when parsed
+            Event::Midi(message) => message.to_bytes().ok(),
+            Event::SysEx(data) => Some([&[0xf0], &data[..]].concat()),
+            Event::Escape(data) => Some(data.clone()),
+            Event::Meta(_) => None,
+        }
+    }
+}
+
+/// Meta event, not sent to devices.
+///
+/// Text is kept as is, as files often use encodings other than UTF-8.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetaEvent {
+    /// Number of the sequence, `None` for the position of the track in the
+    /// file.
+    SequenceNumber(Option<u16>),
+    Text(Vec<u8>),
+    Copyright(Vec<u8>),
+    TrackName(Vec<u8>),
+    InstrumentName(Vec<u8>),
+    Lyric(Vec<u8>),
+    Marker(Vec<u8>),
+    CuePoint(Vec<u8>),
+    /// Channel (0 based) of the following SysEx and meta events.
+    ChannelPrefix(u8),
+    /// Port of the following events.
+    Port(u8),
+    EndOfTrack,
+    /// Microseconds per quarter note.
+    Tempo(u32),
+    SmpteOffset {
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        frames: u8,
+        subframes: u8,
+    },
+    /// Time signature, the denominator being a power of 2, e.g., 3 for
+    /// eighth notes.
+    TimeSignature {
+        numerator: u8,
+        denominator: u8,
+        clocks_per_click: u8,
+        thirty_seconds_per_quarter: u8,
+    },
+    /// Key signature, sharps being positive and flats negative.
+    KeySignature {
+        sharps: i8,
+        minor: bool,
+    },
+    SequencerSpecific(Vec<u8>),
+    /// Meta event of a type not listed above.
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Smf {
+    /// Parse the contents of a file, e.g., as read by `std::fs::read`.
+    ///
+    /// Chunks other than the header and tracks are skipped, as required by
+    /// the specification, as is data following the last track.
+    pub fn parse(data: &[u8]) -> Result<Self, MidiError> {
+        if !data.starts_with(b"MThd") {
+            return Err(invalid(0, "missing MThd header"));
+        }
+        let mut reader = Reader {
+            data,
+            pos: 0,
+            offset: 0,
+        };
+        let header = reader.chunk()?.1.data;
+        if header.len() < 6 {
+            return Err(invalid(8, "header too short"));
+        }
+        let format = match u16::from_be_bytes([header[0], header[1]]) {
+            0 => Format::SingleTrack,
+            1 => Format::Simultaneous,
+            2 => Format::Sequential,
+            _ => return Err(invalid(8, "unknown format")),
+        };
+        let track_count = u16::from_be_bytes([header[2], header[3]]);
+        if format == Format::SingleTrack && track_count != 1 {
+            return Err(invalid(10, "format 0 with other than one track"));
+        }
+        let timing = match header[4] {
+            0x00..=0x7f => Timing::Metrical(u16::from_be_bytes([header[4], header[5]])),
+            // negative frames per second
+            fps => match fps.wrapping_neg() {
+                fps @ (24 | 25 | 29 | 30) => Timing::Timecode {
+                    fps,
+                    ticks_per_frame: header[5],
+                },
+                _ => return Err(invalid(12, "unknown SMPTE format")),
+            },
+        };
+
+        let mut tracks = vec![];
+        while tracks.len() < track_count as usize {
+            if reader.pos == data.len() {
+                return Err(invalid(reader.pos, "missing track"));
+            }
+            let (kind, chunk) = reader.chunk()?;
+            if kind == *b"MTrk" {
+                tracks.push(chunk.track()?);
+            }
+        }
+        Ok(Smf {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// The contents of a file, e.g., to be written by `std::fs::write`.
+    ///
+    /// Channel messages are written with running status, other messages of
+    /// [`Event::Midi`] as escape events. [`MetaEvent::EndOfTrack`] is
+    /// appended to tracks not ending with it.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MidiError> {
+        let track_count = u16::try_from(self.tracks.len())
+            .ok()
+            .filter(|count| self.format != Format::SingleTrack || *count == 1)
+            .ok_or(MidiError::InvalidMessage {
+                field: "track count",
+                value: self.tracks.len() as u32,
+            })?;
+        let format: u16 = match self.format {
+            Format::SingleTrack => 0,
+            Format::Simultaneous => 1,
+            Format::Sequential => 2,
+        };
+        let division = match self.timing {
+            Timing::Metrical(ticks) if ticks <= 0x7fff => ticks.to_be_bytes(),
+            Timing::Metrical(ticks) => {
+                return Err(MidiError::InvalidMessage {
+                    field: "ticks per quarter note",
+                    value: ticks as u32,
+                });
+            }
+            Timing::Timecode {
+                fps: fps @ (24 | 25 | 29 | 30),
+                ticks_per_frame,
+            } => [fps.wrapping_neg(), ticks_per_frame],
+            Timing::Timecode { fps, .. } => {
+                return Err(MidiError::InvalidMessage {
+                    field: "frames per second",
+                    value: fps as u32,
+                });
+            }
+        };
+
+        let mut data = b"MThd\0\0\0\x06".to_vec();
+        data.extend_from_slice(&format.to_be_bytes());
+        data.extend_from_slice(&track_count.to_be_bytes());
+        data.extend_from_slice(&division);
+        for track in &self.tracks {
+            let chunk = track.to_bytes()?;
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
+    }
+}
+
+impl Track {
+    /// The data of the track chunk.
+    fn to_bytes(&self) -> Result<Vec<u8>, MidiError> {
+        let mut data = vec![];
+        let mut encoder = MidiEncoder::new(true);
+        for TrackEvent { delta, event } in &self.events {
+            write_varlen(*delta, "delta time", &mut data)?;
+            match event {
+                Event::Midi(message) if message.channel_number().is_some() => {
+                    encoder.encode(message, &mut data)?
+                }
+                event => {
+                    // SysEx and meta events cancel running status
+                    encoder = MidiEncoder::new(true);
+                    let (status, bytes) = match event {
+                        Event::Midi(message) => (0xf7, message.to_bytes()?),
+                        Event::SysEx(bytes) => (0xf0, bytes.clone()),
+                        Event::Escape(bytes) => (0xf7, bytes.clone()),
+                        Event::Meta(meta) => {
+                            let (kind, bytes) = meta.to_bytes()?;
+                            data.extend_from_slice(&[0xff, kind]);
+                            write_varlen(bytes.len() as u32, "length", &mut data)?;
+                            data.extend_from_slice(&bytes);
+                            continue;
+                        }
+                    };
+                    data.push(status);
+                    write_varlen(bytes.len() as u32, "length", &mut data)?;
+                    data.extend_from_slice(&bytes);
+                }
+            }
+        }
+        if self.events.last().map(|event| &event.event) != Some(&Event::Meta(MetaEvent::EndOfTrack))
+        {
+            data.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        }
+        Ok(data)
+    }
+}
+
+impl MetaEvent {
+    /// Type and data of the meta event.
+    fn to_bytes(&self) -> Result<(u8, Vec<u8>), MidiError> {
+        Ok(match self {
+            MetaEvent::SequenceNumber(None) => (0x00, vec![]),
+            MetaEvent::SequenceNumber(Some(number)) => (0x00, number.to_be_bytes().to_vec()),
+            MetaEvent::Text(text) => (0x01, text.clone()),
+            MetaEvent::Copyright(text) => (0x02, text.clone()),
+            MetaEvent::TrackName(text) => (0x03, text.clone()),
+            MetaEvent::InstrumentName(text) => (0x04, text.clone()),
+            MetaEvent::Lyric(text) => (0x05, text.clone()),
+            MetaEvent::Marker(text) => (0x06, text.clone()),
+            MetaEvent::CuePoint(text) => (0x07, text.clone()),
+            MetaEvent::ChannelPrefix(channel) if *channel < 16 => (0x20, vec![*channel]),
+            MetaEvent::ChannelPrefix(channel) => {
+                return Err(MidiError::InvalidMessage {
+                    field: "channel",
+                    value: *channel as u32,
+                });
+            }
+            MetaEvent::Port(port) => (0x21, vec![*port]),
+            MetaEvent::EndOfTrack => (0x2f, vec![]),
+            MetaEvent::Tempo(tempo) if *tempo <= 0xff_ffff => {
+                (0x51, tempo.to_be_bytes()[1..].to_vec())
+            }
+            MetaEvent::Tempo(tempo) => {
+                return Err(MidiError::InvalidMessage {
+                    field: "tempo",
+                    value: *tempo,
+                });
+            }
+            MetaEvent::SmpteOffset {
+                hours,
+                minutes,
+                seconds,
+                frames,
+                subframes,
+            } => (0x54, vec![*hours, *minutes, *seconds, *frames, *subframes]),
+            MetaEvent::TimeSignature {
+                numerator,
+                denominator,
+                clocks_per_click,
+                thirty_seconds_per_quarter,
+            } => (
+                0x58,
+                vec![
+                    *numerator,
+                    *denominator,
+                    *clocks_per_click,
+                    *thirty_seconds_per_quarter,
+                ],
+            ),
+            MetaEvent::KeySignature { sharps, minor } => (0x59, vec![*sharps as u8, *minor as u8]),
+            MetaEvent::SequencerSpecific(data) => (0x7f, data.clone()),
+            MetaEvent::Unknown { kind, data } => (*kind, data.clone()),
+        })
+    }
+}
+
+/// Append `value` of `field` as a variable length quantity.
+fn write_varlen(value: u32, field: &'static str, data: &mut Vec<u8>) -> Result<(), MidiError> {
+    if value > 0x0fff_ffff {
+        return Err(MidiError::InvalidMessage { field, value });
+    }
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push(0x80 | (value >> shift & 0x7f) as u8);
+        shift -= 7;
+    }
+    data.push((value & 0x7f) as u8);
+    Ok(())
+}
+
+/// Position in the data being parsed, a chunk starting at `offset` in the
+/// file or the file itself.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Error at `pos` in the data.
+    fn invalid(&self, pos: usize, reason: &'static str) -> MidiError {
+        invalid(self.offset + pos, reason)
+    }
+
+    fn bytes(&mut self, len: usize, reason: &'static str) -> Result<&'a [u8], MidiError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or(self.invalid(self.data.len(), reason))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self, reason: &'static str) -> Result<u8, MidiError> {
+        Ok(self.bytes(1, reason)?[0])
+    }
+
+    /// Type and data of the next chunk, with the offset of the data.
+    fn chunk(&mut self) -> Result<([u8; 4], Reader<'a>), MidiError> {
+        let header = self.bytes(8, "truncated chunk header")?;
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let offset = self.offset + self.pos;
+        let data = self.bytes(len as usize, "truncated chunk")?;
+        let kind = [header[0], header[1], header[2], header[3]];
+        Ok((
+            kind,
+            Reader {
+                data,
+                pos: 0,
+                offset,
+            },
+        ))
+    }
+
+    /// Variable length quantity of up to 4 bytes, 7 bits each.
+    fn varlen(&mut self) -> Result<u32, MidiError> {
+        let start = self.pos;
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte("truncated variable length quantity")?;
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(self.invalid(start, "variable length quantity longer than 4 bytes"))
+    }
+
+    /// Data of a SysEx, escape or meta event, preceded by its length.
+    fn varlen_bytes(&mut self) -> Result<&'a [u8], MidiError> {
+        let len = self.varlen()?;
+        self.bytes(len as usize, "truncated event")
+    }
+
+    /// Parse the data of a track chunk.
+    fn track(mut self) -> Result<Track, MidiError> {
+        let mut events = vec![];
+        let mut running_status = None;
+        loop {
+            if self.pos == self.data.len() {
+                return Err(self.invalid(self.pos, "missing end of track"));
+            }
+            let delta = self.varlen()?;
+            let status_pos = self.pos;
+            let mut status = self.byte("truncated event")?;
+            // SysEx and meta events cancel running status
+            let event = match status {
+                0xf0 => {
+                    running_status = None;
+                    Event::SysEx(self.varlen_bytes()?.to_vec())
+                }
+                0xf7 => {
+                    running_status = None;
+                    Event::Escape(self.varlen_bytes()?.to_vec())
+                }
+                0xff => {
+                    running_status = None;
+                    let kind = self.byte("truncated meta event")?;
+                    let data = self.varlen_bytes()?;
+                    Event::Meta(
+                        meta_event(kind, data)
+                            .ok_or(self.invalid(status_pos, "invalid meta event"))?,
+                    )
+                }
+                0xf1..=0xfe => return Err(self.invalid(status_pos, "invalid status byte")),
+                _ => {
+                    let mut data = [0; 2];
+                    let mut len = 0;
+                    if status < 0x80 {
+                        // the byte read is the first data byte
+                        data[0] = status;
+                        len = 1;
+                        status = running_status
+                            .ok_or(self.invalid(status_pos, "data byte without running status"))?;
+                    }
+                    running_status = Some(status);
+                    while len < message::data_len(status) {
+                        data[len] = self.byte("truncated event")?;
+                        if data[len] >= 0x80 {
+                            return Err(self.invalid(self.pos - 1, "invalid data byte"));
+                        }
+                        len += 1;
+                    }
+                    Event::Midi(message::message(status, data))
+                }
+            };
+            let end = event == Event::Meta(MetaEvent::EndOfTrack);
+            events.push(TrackEvent { delta, event });
+            if end {
+                if self.pos < self.data.len() {
+                    return Err(self.invalid(self.pos, "event after end of track"));
+                }
+                return Ok(Track { events });
+            }
+        }
+    }
+}
+
+/// The meta event of `kind` with `data`, `None` if the data doesn't fit.
+fn meta_event(kind: u8, data: &[u8]) -> Option<MetaEvent> {
+    let text = || data.to_vec();
+    Some(match (kind, data) {
+        (0x00, []) => MetaEvent::SequenceNumber(None),
+        (0x00, [msb, lsb]) => MetaEvent::SequenceNumber(Some(u16::from_be_bytes([*msb, *lsb]))),
+        (0x00, _) => return None,
+        (0x01, _) => MetaEvent::Text(text()),
+        (0x02, _) => MetaEvent::Copyright(text()),
+        (0x03, _) => MetaEvent::TrackName(text()),
+        (0x04, _) => MetaEvent::InstrumentName(text()),
+        (0x05, _) => MetaEvent::Lyric(text()),
+        (0x06, _) => MetaEvent::Marker(text()),
+        (0x07, _) => MetaEvent::CuePoint(text()),
+        (0x20, [channel]) if *channel < 16 => MetaEvent::ChannelPrefix(*channel),
+        (0x21, [port]) => MetaEvent::Port(*port),
+        (0x2f, []) => MetaEvent::EndOfTrack,
+        (0x51, [a, b, c]) => MetaEvent::Tempo(u32::from_be_bytes([0, *a, *b, *c])),
+        (0x54, [hours, minutes, seconds, frames, subframes]) => MetaEvent::SmpteOffset {
+            hours: *hours,
+            minutes: *minutes,
+            seconds: *seconds,
+            frames: *frames,
+            subframes: *subframes,
+        },
+        (
+            0x58,
+            [
+                numerator,
+                denominator,
+                clocks_per_click,
+                thirty_seconds_per_quarter,
+            ],
+        ) => MetaEvent::TimeSignature {
+            numerator: *numerator,
+            denominator: *denominator,
+            clocks_per_click: *clocks_per_click,
+            thirty_seconds_per_quarter: *thirty_seconds_per_quarter,
+        },
+        (0x59, [sharps, minor @ (0 | 1)]) => MetaEvent::KeySignature {
+            sharps: *sharps as i8,
+            minor: *minor == 1,
+        },
+        (0x7f, _) => MetaEvent::SequencerSpecific(text()),
+        (0x20 | 0x21 | 0x2f | 0x51 | 0x54 | 0x58 | 0x59, _) => return None,
+        (kind, _) => MetaEvent::Unknown { kind, data: text() },
+    })
+}
+
+fn invalid(offset: usize, reason: &'static str) -> MidiError {
+    MidiError::InvalidFile { offset, reason }
+}
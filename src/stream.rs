@@ -0,0 +1,76 @@
+//! Async streams of incoming data and notifications, and async sending,
+//! behind the `async` feature.
+//!
+//! Streams are backed by unbounded channels filled by the regular input path
+//! and notifications, so they work with any executor, e.g., tokio. They are
+//! `Unpin`, so `StreamExt::next` can be used directly. Sending
+//! is offloaded to a thread pool, as sending (e.g., long SysEx messages on
+//! WinMM) may block.
+
+use crate::channel::MidiEvent;
+use crate::error::MidiError;
+use crate::midi_con::{ArcMutexMidiCon, EndpointId, Notification};
+
+use futures_core::Stream;
+
+impl ArcMutexMidiCon {
+    /// Connect to a MIDI source by its id, returning a stream of its incoming
+    /// data
+    ///
+    /// The source stays connected when the stream is dropped, until
+    /// disconnected by [`ArcMutexMidiCon::disconnect_source`].
+    pub fn source_stream(
+        &self,
+        source: EndpointId,
+    ) -> Result<impl Stream<Item = MidiEvent> + Send + Unpin + 'static, MidiError> {
+        let (sender, receiver) = async_channel::unbounded();
+        self.connect_source(source, move |timestamp, data, _| {
+            // the stream may be gone, the source stays connected
+            let _ = sender.try_send(MidiEvent {
+                source,
+                timestamp,
+                data: data.into(),
+            });
+        })?;
+        Ok(Box::pin(receiver))
+    }
+
+    /// Connect to a MIDI source by its index, returning a stream of its
+    /// incoming data
+    pub fn source_stream_by_index(
+        &self,
+        source_index: usize,
+    ) -> Result<
+        (
+            EndpointId,
+            impl Stream<Item = MidiEvent> + Send + Unpin + 'static,
+        ),
+        MidiError,
+    > {
+        let source = self.source_by_index(source_index)?;
+        Ok((source, self.source_stream(source)?))
+    }
+
+    /// Stream of notifications, as passed to the notification callback.
+    ///
+    /// Any number of streams may be created, alongside the callback.
+    pub fn notifications(&self) -> impl Stream<Item = Notification> + Send + Unpin + 'static {
+        let (sender, receiver) = async_channel::unbounded();
+        self.0
+            .lock()
+            .unwrap()
+            .notification_subscribers
+            .push(Box::new(move |notification| {
+                sender.try_send(notification.clone()).is_ok()
+            }));
+        Box::pin(receiver)
+    }
+
+    /// Send raw MIDI data to a connected destination by its id, without
+    /// blocking the executor
+    pub async fn send_async(&self, destination: EndpointId, data: &[u8]) -> Result<(), MidiError> {
+        let midi_con = self.clone();
+        let data = data.to_vec();
+        blocking::unblock(move || midi_con.send(destination, &data)).await
+    }
+}
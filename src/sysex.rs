@@ -0,0 +1,209 @@
+//! Reassembly of SysEx messages split over several chunks of incoming data.
+//!
+//! Backends deliver long SysEx messages in pieces, e.g., one CoreMIDI packet or
+//! ALSA event at a time. [`SysExAssembler`] buffers the pieces, so callbacks
+//! of [`ArcMutexMidiCon`](crate::midi_con::ArcMutexMidiCon) receive each SysEx
+//! message as a whole, from `0xf0` up to and including `0xf7`.
+//!
+//! In the other direction, [`SysExSplitter`] splits outgoing data at the
+//! boundaries of SysEx messages, for backends sending SysEx apart from other
+//! messages, e.g., as ALSA sequencer events of their own.
+
+use crate::error::MidiError;
+
+use std::time::Duration;
+
+/// Limits of SysEx messages reassembled from incoming data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SysExLimits {
+    /// Maximum size of a SysEx message, including `0xf0` and `0xf7`
+    pub max_size: usize,
+    /// Maximum time between the pieces of a SysEx message, `None` for no limit
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SysExLimits {
+    /// 1 MiB, and a timeout of one second.
+    fn default() -> Self {
+        SysExLimits {
+            max_size: 1 << 20,
+            timeout: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Reassembler of SysEx messages, see the [module](self) documentation.
+///
+/// Data other than SysEx is passed on as it arrives, including realtime
+/// messages interleaved with a SysEx message. A SysEx message is dropped,
+/// reporting an error, if interrupted by another status byte, exceeding the
+/// maximum size, or if its next piece arrives after the timeout.
+#[derive(Clone, Debug)]
+pub struct SysExAssembler {
+    limits: SysExLimits,
+    state: State,
+    /// Timestamp of the last piece of data
+    last: Duration,
+}
+
+#[derive(Clone, Debug)]
+enum State {
+    /// Outside of a SysEx message
+    Idle,
+    /// Within a SysEx message, with the timestamp of its start
+    Receiving(Duration, Vec<u8>),
+    /// Within a dropped SysEx message, dropping data bytes up to the next
+    /// status byte
+    Dropping,
+}
+
+impl SysExAssembler {
+    pub fn new(limits: SysExLimits) -> Self {
+        SysExAssembler {
+            limits,
+            state: State::Idle,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Process a piece of incoming `data` received at `timestamp`.
+    ///
+    /// `deliver` is called for each SysEx message completed, and for the other
+    /// data, in the order received, `error` for each SysEx message dropped.
+    pub fn push(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        mut deliver: impl FnMut(Duration, &[u8]),
+        mut error: impl FnMut(MidiError),
+    ) {
+        if let State::Receiving(..) = self.state
+            && let Some(timeout) = self.limits.timeout
+            && timestamp.saturating_sub(self.last) > timeout
+        {
+            error(MidiError::SysExTimeout);
+            self.state = State::Dropping;
+        }
+        self.last = timestamp;
+
+        // data other than SysEx, delivered before the next SysEx message
+        let mut other = vec![];
+        for &byte in data {
+            if byte >= 0xf8 {
+                // realtime, may be interleaved with SysEx
+                other.push(byte);
+                continue;
+            }
+            match &mut self.state {
+                State::Receiving(start, sysex) => {
+                    if byte == 0xf7 {
+                        sysex.push(byte);
+                        if !other.is_empty() {
+                            deliver(timestamp, &other);
+                            other.clear();
+                        }
+                        deliver(*start, sysex);
+                        self.state = State::Idle;
+                        continue;
+                    }
+                    if byte < 0x80 {
+                        // leave room for the final 0xf7
+                        if sysex.len() + 2 > self.limits.max_size {
+                            error(MidiError::SysExTooLong(self.limits.max_size));
+                            self.state = State::Dropping;
+                        } else {
+                            sysex.push(byte);
+                        }
+                        continue;
+                    }
+                    error(MidiError::SysExInterrupted);
+                    self.state = State::Idle;
+                }
+                State::Dropping => {
+                    if byte < 0x80 {
+                        continue;
+                    }
+                    self.state = State::Idle;
+                    if byte == 0xf7 {
+                        continue;
+                    }
+                }
+                State::Idle => {}
+            }
+            if byte == 0xf0 {
+                if !other.is_empty() {
+                    deliver(timestamp, &other);
+                    other.clear();
+                }
+                self.state = State::Receiving(timestamp, vec![byte]);
+            } else {
+                other.push(byte);
+            }
+        }
+        if !other.is_empty() {
+            deliver(timestamp, &other);
+        }
+    }
+}
+
+/// Piece of outgoing data, see [`SysExSplitter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Piece<'a> {
+    /// A SysEx message, or a part of it
+    SysEx(&'a [u8]),
+    /// Other messages, or a realtime message interleaved with a SysEx message
+    Other(&'a [u8]),
+}
+
+/// Splitter of outgoing data at the boundaries of SysEx messages, see the
+/// [module](self) documentation.
+///
+/// SysEx messages are passed on as they are, so a message split over several
+/// chunks of data is sent in pieces rather than held back. A SysEx message
+/// ends with `0xf7`, or any other status byte but realtime.
+#[derive(Clone, Debug, Default)]
+pub struct SysExSplitter {
+    /// Within a SysEx message continued by the next chunk
+    in_sysex: bool,
+}
+
+impl SysExSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split the next chunk of outgoing `data` into pieces.
+    pub fn split<'a>(&'a mut self, data: &'a [u8]) -> impl Iterator<Item = Piece<'a>> + 'a {
+        let mut data = data;
+        std::iter::from_fn(move || {
+            let &first = data.first()?;
+            if first == 0xf0 || (self.in_sysex && (first < 0x80 || first == 0xf7)) {
+                // up to and including 0xf7, or up to any other status byte
+                let skip = usize::from(first == 0xf0);
+                let len = data[skip..]
+                    .iter()
+                    .position(|byte| *byte >= 0x80)
+                    .map_or(data.len(), |end| {
+                        skip + end + usize::from(data[skip + end] == 0xf7)
+                    });
+                let (sysex, rest) = data.split_at(len);
+                self.in_sysex =
+                    sysex.last() != Some(&0xf7) && rest.first().is_none_or(|byte| *byte >= 0xf8);
+                data = rest;
+                return Some(Piece::SysEx(sysex));
+            }
+            // realtime bytes may be interleaved with a SysEx message
+            let len = if self.in_sysex && first >= 0xf8 {
+                1
+            } else {
+                self.in_sysex = false;
+                data.iter()
+                    .position(|byte| *byte == 0xf0)
+                    .unwrap_or(data.len())
+            };
+            let (other, rest) = data.split_at(len);
+            data = rest;
+            Some(Piece::Other(other))
+        })
+    }
+}
@@ -0,0 +1,122 @@
+//! Virtual sources and destinations, published by the application for other
+//! applications (e.g., a DAW) to connect to.
+//!
+//! Virtual endpoints are supported on CoreMIDI, where they are listed along
+//! with the other endpoints, and on ALSA, where they are ports of the
+//! connection's own sequencer clients and thus not listed. WinMM has no
+//! virtual endpoints, creating one fails with [`MidiError::Unsupported`].
+
+use crate::backend::{InputConnection, OutputConnection};
+use crate::error::MidiError;
+use crate::message::MidiMessage;
+use crate::midi_con::{ArcMutexMidiCon, EndpointId, Notification, SourceCallback};
+use log::trace;
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// A virtual source, removed when dropped.
+pub struct VirtualSource {
+    id: EndpointId,
+    name: String,
+    connection: Box<dyn OutputConnection>,
+}
+
+impl VirtualSource {
+    /// Id of the source, as seen by other applications.
+    pub fn id(&self) -> EndpointId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Send raw MIDI data to the applications connected to the source.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.connection.send(data)
+    }
+
+    /// Send a MIDI message to the applications connected to the source.
+    ///
+    /// Fails with [`MidiError::InvalidMessage`] if a field of the message is out
+    /// of range.
+    pub fn send_message(&mut self, message: &MidiMessage) -> Result<(), MidiError> {
+        self.send(&message.to_bytes()?)
+    }
+}
+
+/// A virtual destination, removed when dropped.
+pub struct VirtualDestination {
+    id: EndpointId,
+    name: String,
+    // the destination is removed when the connection is dropped
+    #[allow(dead_code)]
+    connection: Box<dyn InputConnection>,
+}
+
+impl VirtualDestination {
+    /// Id of the destination, as seen by other applications.
+    pub fn id(&self) -> EndpointId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl ArcMutexMidiCon {
+    /// Create a virtual source named `name`, which other applications can
+    /// connect to, sending through the returned handle.
+    pub fn create_virtual_source(&self, name: &str) -> Result<VirtualSource, MidiError> {
+        let (id, connection) = self.0.lock().unwrap().backend.create_virtual_source(name)?;
+        trace!("Created virtual source {:?}: {}", id, name);
+        // list the source right away, rather than once notified by the backend
+        self.update_connections(&Notification::SetupChanged);
+        Ok(VirtualSource {
+            id,
+            name: name.to_string(),
+            connection,
+        })
+    }
+
+    /// Create a virtual destination named `name`, which other applications can
+    /// send to, with a callback for incoming data and the time it was received.
+    ///
+    /// As for connected sources, SysEx messages are reassembled before the
+    /// callback is invoked, and dropped ones are reported as
+    /// [`Notification::InputError`] with the id of the destination.
+    pub fn create_virtual_destination(
+        &self,
+        name: &str,
+        cb: impl Fn(Duration, &[u8], &ArcMutexMidiCon) + Send + 'static,
+    ) -> Result<VirtualDestination, MidiError> {
+        let (id, connection) = {
+            let midi_con = &mut *self.0.lock().unwrap();
+            let callback: Arc<Mutex<SourceCallback>> = Arc::new(Mutex::new(Box::new(cb)));
+            // the id is only known once created, but data may arrive right away
+            let key = Arc::new(OnceLock::new());
+            let input_callback = self.input_callback(
+                {
+                    let key = key.clone();
+                    move || *key.wait()
+                },
+                midi_con.sysex_limits,
+                &callback,
+            );
+            let (id, connection) = midi_con
+                .backend
+                .create_virtual_destination(name, input_callback)?;
+            key.set(id).unwrap();
+            (id, connection)
+        };
+        trace!("Created virtual destination {:?}: {}", id, name);
+        self.update_connections(&Notification::SetupChanged);
+        Ok(VirtualDestination {
+            id,
+            name: name.to_string(),
+            connection,
+        })
+    }
+}
@@ -1 +1,3 @@
-pub mod midi_con;
+mod win_mm;
+
+pub use win_mm::*;
@@ -0,0 +1,516 @@
+use ::windows::Win32::Media::Audio::{
+    CALLBACK_FUNCTION, CALLBACK_NULL, HMIDIIN, HMIDIOUT, MIDIERR_NODEVICE, MIDIERR_STILLPLAYING,
+    MIDIHDR, MIDIINCAPSW, MIDIOUTCAPSW, midiInAddBuffer, midiInClose, midiInGetDevCapsW,
+    midiInGetNumDevs, midiInOpen, midiInPrepareHeader, midiInReset, midiInStart, midiInStop,
+    midiInUnprepareHeader, midiOutClose, midiOutGetDevCapsW, midiOutGetNumDevs, midiOutLongMsg,
+    midiOutOpen, midiOutPrepareHeader, midiOutReset, midiOutShortMsg, midiOutUnprepareHeader,
+};
+use ::windows::Win32::Media::{
+    MM_MIM_DATA, MM_MIM_LONGDATA, MMSYSERR_BADDEVICEID, MMSYSERR_NODRIVER, MMSYSERR_NOERROR,
+};
+use ::windows::core::PSTR;
+use log::{trace, warn};
+
+use std::cell::UnsafeCell;
+use std::mem::size_of;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    HandlerSender, InputCallback, InputConnection, MidiBackend, NotificationHandler,
+    OutputConnection, notification_channel,
+};
+use crate::error::MidiError;
+use crate::midi_con::{EndpointId, Notification};
+
+/// Size of each buffer handed to the driver for receiving SysEx.
+const SYSEX_BUFFER_SIZE: usize = 4096;
+
+/// Number of SysEx buffers per input, received SysEx is split over them.
+const SYSEX_BUFFER_COUNT: usize = 4;
+
+/// WinMM has no device notifications, so the device lists are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const MIDIHDR_SIZE: u32 = size_of::<MIDIHDR>() as u32;
+
+/// MIDI backend on top of the Windows Multimedia (WinMM) MIDI API.
+///
+/// Sources are the MIDI in and destinations the MIDI out devices. WinMM does
+/// not report devices being added or removed, instead the device lists are
+/// polled by a background thread, notifying on any change. Incoming data is
+/// timestamped by the driver, in milliseconds since the input was started, and
+/// passed to the callback on a thread of its own per input.
+pub struct WinMmBackend {
+    /// Creation of the backend, the session epoch
+    epoch: Instant,
+    handler_tx: HandlerSender,
+    running: Arc<AtomicBool>,
+}
+
+impl WinMmBackend {
+    /// Create the backend, the client name is not used by WinMM.
+    pub fn new(_client_name: &str) -> Result<Self, MidiError> {
+        let (handler_tx, mut handler_rx) = notification_channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        thread::spawn(move || {
+            let mut devices = (input_names(), output_names());
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                let current = (input_names(), output_names());
+                if current != devices {
+                    trace!("devices changed: {:?}", current);
+                    devices = current;
+                    handler_rx.notify(&Notification::SetupChanged);
+                }
+            }
+        });
+
+        Ok(WinMmBackend {
+            epoch: Instant::now(),
+            handler_tx,
+            running,
+        })
+    }
+}
+
+impl Drop for WinMmBackend {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn input_names() -> Vec<String> {
+    (0..unsafe { midiInGetNumDevs() } as usize)
+        .map(|device_id| {
+            let mut caps = MIDIINCAPSW::default();
+            let result =
+                unsafe { midiInGetDevCapsW(device_id, &mut caps, size_of::<MIDIINCAPSW>() as u32) };
+            if result == MMSYSERR_NOERROR {
+                // copy out of the packed struct
+                let name = caps.szPname;
+                from_wide(&name)
+            } else {
+                "Unknown".to_string()
+            }
+        })
+        .collect()
+}
+
+fn output_names() -> Vec<String> {
+    (0..unsafe { midiOutGetNumDevs() } as usize)
+        .map(|device_id| {
+            let mut caps = MIDIOUTCAPSW::default();
+            let result = unsafe {
+                midiOutGetDevCapsW(device_id, &mut caps, size_of::<MIDIOUTCAPSW>() as u32)
+            };
+            if result == MMSYSERR_NOERROR {
+                // copy out of the packed struct
+                let name = caps.szPname;
+                from_wide(&name)
+            } else {
+                "Unknown".to_string()
+            }
+        })
+        .collect()
+}
+
+/// Device names with their ids.
+///
+/// WinMM device ids are positions, which shift as devices come and go, so the
+/// id is derived from the name and the ordinal among devices of the same name.
+fn with_ids(names: Vec<String>) -> Vec<(EndpointId, String)> {
+    let mut endpoints: Vec<(EndpointId, String)> = vec![];
+    for name in names {
+        let ordinal = endpoints.iter().filter(|(_, other)| *other == name).count();
+        let id = fnv1a(
+            name.bytes()
+                .chain([0])
+                .chain((ordinal as u32).to_le_bytes()),
+        );
+        endpoints.push((EndpointId(id), name));
+    }
+    endpoints
+}
+
+/// 64 bit FNV-1a hash of `bytes`, which unlike the hasher of std stays the same
+/// across releases, as ids are persisted in session configs.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// WinMM device id of the endpoint `id` in `names`.
+fn device_id(names: Vec<String>, id: EndpointId) -> Result<usize, MidiError> {
+    with_ids(names)
+        .iter()
+        .position(|(endpoint_id, _)| *endpoint_id == id)
+        .ok_or(MidiError::UnknownEndpoint(id))
+}
+
+fn from_wide(name: &[u16]) -> String {
+    let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+    String::from_utf16_lossy(&name[..len])
+}
+
+/// Map the MMRESULT of `call` to a [`MidiError`], unless `MMSYSERR_NOERROR`.
+fn check(result: u32, call: &'static str) -> Result<(), MidiError> {
+    match result {
+        MMSYSERR_NOERROR => Ok(()),
+        // the device was unplugged
+        MMSYSERR_BADDEVICEID | MMSYSERR_NODRIVER | MIDIERR_NODEVICE => Err(MidiError::DeviceGone),
+        status => Err(MidiError::Backend {
+            call,
+            status: status as i32,
+        }),
+    }
+}
+
+/// Length of a (non SysEx) MIDI message, given its status byte.
+fn message_len(status: u8) -> usize {
+    match status {
+        0x80..=0xbf | 0xe0..=0xef | 0xf2 => 3,
+        0xc0..=0xdf | 0xf1 | 0xf3 => 2,
+        _ => 1,
+    }
+}
+
+impl MidiBackend for WinMmBackend {
+    fn sources(&self) -> Vec<(EndpointId, String)> {
+        with_ids(input_names())
+    }
+
+    fn destinations(&self) -> Vec<(EndpointId, String)> {
+        with_ids(output_names())
+    }
+
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn set_notification_handler(&mut self, handler: NotificationHandler) {
+        self.handler_tx.install(handler);
+    }
+
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError> {
+        let source_index = device_id(input_names(), source)?;
+        let (events, events_rx) = mpsc::channel();
+        let state = Arc::new(InputState {
+            events,
+            handle: Mutex::new(None),
+            buffers: (0..SYSEX_BUFFER_COUNT)
+                .map(|_| SysExBuffer {
+                    header: UnsafeCell::new(MIDIHDR::default()),
+                    data: UnsafeCell::new([0; SYSEX_BUFFER_SIZE]),
+                })
+                .collect(),
+        });
+        let mut handle = HMIDIIN::default();
+        check(
+            unsafe {
+                midiInOpen(
+                    &mut handle,
+                    source_index as u32,
+                    Some(midi_in_proc as *const () as usize),
+                    Some(Arc::as_ptr(&state) as usize),
+                    CALLBACK_FUNCTION,
+                )
+            },
+            "midiInOpen",
+        )?;
+        *state.handle.lock().unwrap() = Some(handle);
+        // from here on, the device is closed when the input is dropped
+        let input = WinMmInput {
+            state: state.clone(),
+        };
+
+        for buffer in &state.buffers {
+            let header = buffer.header.get();
+            unsafe {
+                (*header).lpData = PSTR(buffer.data.get().cast());
+                (*header).dwBufferLength = SYSEX_BUFFER_SIZE as u32;
+            }
+            check(
+                unsafe { midiInPrepareHeader(handle, header, MIDIHDR_SIZE) },
+                "midiInPrepareHeader",
+            )?;
+            check(
+                unsafe { midiInAddBuffer(handle, header, MIDIHDR_SIZE) },
+                "midiInAddBuffer",
+            )?;
+        }
+        let start = self.now();
+        thread::spawn(move || receive(state, events_rx, callback, start));
+        check(unsafe { midiInStart(handle) }, "midiInStart")?;
+        trace!(
+            "Connected to source: {}",
+            input_names()
+                .get(source_index)
+                .map_or("Unknown", |name| name.as_str())
+        );
+
+        Ok(Box::new(input))
+    }
+
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError> {
+        let destination_index = device_id(output_names(), destination)?;
+        let mut handle = HMIDIOUT::default();
+        check(
+            unsafe {
+                midiOutOpen(
+                    &mut handle,
+                    destination_index as u32,
+                    None,
+                    None,
+                    CALLBACK_NULL,
+                )
+            },
+            "midiOutOpen",
+        )?;
+        trace!(
+            "Connected to destination: {}",
+            output_names()
+                .get(destination_index)
+                .map_or("Unknown", |name| name.as_str())
+        );
+        Ok(Box::new(WinMmOutput {
+            handle,
+            in_sysex: false,
+        }))
+    }
+}
+
+/// Data received by the driver, handed from `midi_in_proc` to the input
+/// thread, with the driver timestamp in milliseconds.
+enum InputEvent {
+    /// A short message, packed into the low bytes
+    Short(u32, u32),
+    /// A SysEx buffer returned by the driver, by header address
+    Long(usize, u32),
+    /// The device was closed
+    Closed,
+}
+
+/// State of an input shared with `midi_in_proc` and the input thread.
+struct InputState {
+    events: mpsc::Sender<InputEvent>,
+    /// Taken when closing, so returned SysEx buffers are not handed out again
+    handle: Mutex<Option<HMIDIIN>>,
+    /// The driver holds on to the headers by address, so the buffers stay in
+    /// place until both the input and its thread are gone
+    buffers: Vec<SysExBuffer>,
+}
+
+// SAFETY: the handle is only used under its lock, and the buffers only by the
+// driver and by the input thread while the driver has returned them.
+unsafe impl Send for InputState {}
+unsafe impl Sync for InputState {}
+
+struct SysExBuffer {
+    header: UnsafeCell<MIDIHDR>,
+    data: UnsafeCell<[u8; SYSEX_BUFFER_SIZE]>,
+}
+
+/// Driver callback, which may call neither WinMM nor user code, so it merely
+/// hands the data over to the input thread.
+extern "system" fn midi_in_proc(
+    _handle: HMIDIIN,
+    message: u32,
+    instance: usize,
+    param1: usize,
+    param2: usize,
+) {
+    // SAFETY: `instance` is the address of the `InputState`, which is kept
+    // alive by `WinMmInput` until the device is closed.
+    let state = unsafe { &*(instance as *const InputState) };
+    let event = match message {
+        MM_MIM_DATA => InputEvent::Short(param1 as u32, param2 as u32),
+        MM_MIM_LONGDATA => InputEvent::Long(param1, param2 as u32),
+        _ => return,
+    };
+    // the input thread runs until after the device is closed
+    let _ = state.events.send(event);
+}
+
+/// Input thread, calling `callback` with the data received and handing the
+/// SysEx buffers back to the driver, until the device is closed.
+fn receive(
+    state: Arc<InputState>,
+    events: mpsc::Receiver<InputEvent>,
+    mut callback: InputCallback,
+    start: Duration,
+) {
+    let mut deliver = |millis: u32, data: &[u8]| {
+        let timestamp = start + Duration::from_millis(millis as u64);
+        // a panicking callback must not take down the input
+        if panic::catch_unwind(AssertUnwindSafe(|| callback(timestamp, data))).is_err() {
+            warn!("Input callback panicked");
+        }
+    };
+    for event in events {
+        match event {
+            InputEvent::Short(packed, millis) => {
+                let bytes = packed.to_le_bytes();
+                deliver(millis, &bytes[..message_len(bytes[0])]);
+            }
+            InputEvent::Long(header, millis) => {
+                let header = header as *mut MIDIHDR;
+                // SAFETY: the header is one of the buffers of `state`, which the
+                // driver has returned
+                let data = unsafe {
+                    std::slice::from_raw_parts(
+                        (*header).lpData.0,
+                        (*header).dwBytesRecorded as usize,
+                    )
+                };
+                if !data.is_empty() {
+                    deliver(millis, data);
+                }
+                if let Some(handle) = *state.handle.lock().unwrap() {
+                    unsafe { midiInAddBuffer(handle, header, MIDIHDR_SIZE) };
+                }
+            }
+            InputEvent::Closed => break,
+        }
+    }
+}
+
+/// An open MIDI in device, the input thread keeping its state alive after it
+/// is dropped, so dropping does not wait for a callback in progress.
+struct WinMmInput {
+    state: Arc<InputState>,
+}
+
+impl InputConnection for WinMmInput {}
+
+impl Drop for WinMmInput {
+    fn drop(&mut self) {
+        if let Some(handle) = self.state.handle.lock().unwrap().take() {
+            unsafe {
+                midiInStop(handle);
+                // returns all pending SysEx buffers
+                midiInReset(handle);
+                for buffer in &self.state.buffers {
+                    midiInUnprepareHeader(handle, buffer.header.get(), MIDIHDR_SIZE);
+                }
+                let result = midiInClose(handle);
+                if result != MMSYSERR_NOERROR {
+                    warn!("midiInClose failed: {}", result);
+                }
+            }
+        }
+        // the thread is not running if opening failed
+        let _ = self.state.events.send(InputEvent::Closed);
+    }
+}
+
+struct WinMmOutput {
+    handle: HMIDIOUT,
+    /// Within a SysEx message continued by the next data sent
+    in_sysex: bool,
+}
+
+// SAFETY: a MIDI out handle is not tied to the thread that opened it.
+unsafe impl Send for WinMmOutput {}
+
+impl WinMmOutput {
+    fn send_short(&self, message: &[u8]) -> Result<(), MidiError> {
+        let mut packed = [0; 4];
+        packed[..message.len()].copy_from_slice(message);
+        check(
+            unsafe { midiOutShortMsg(self.handle, u32::from_le_bytes(packed)) },
+            "midiOutShortMsg",
+        )
+    }
+
+    fn send_long(&self, message: &[u8]) -> Result<(), MidiError> {
+        let mut data = message.to_vec();
+        let mut header = MIDIHDR {
+            lpData: PSTR(data.as_mut_ptr()),
+            dwBufferLength: data.len() as u32,
+            ..Default::default()
+        };
+        unsafe {
+            check(
+                midiOutPrepareHeader(self.handle, &mut header, MIDIHDR_SIZE),
+                "midiOutPrepareHeader",
+            )?;
+            let result = check(
+                midiOutLongMsg(self.handle, &header, MIDIHDR_SIZE),
+                "midiOutLongMsg",
+            );
+            // the data must stay in place until the driver is done with it
+            while midiOutUnprepareHeader(self.handle, &mut header, MIDIHDR_SIZE)
+                == MIDIERR_STILLPLAYING
+            {
+                thread::sleep(Duration::from_millis(1));
+            }
+            result
+        }
+    }
+}
+
+impl OutputConnection for WinMmOutput {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        let mut running_status = None;
+        let mut data = data;
+        while let Some(&first) = data.first() {
+            if first == 0xf0 || (self.in_sysex && (first < 0x80 || first == 0xf7)) {
+                let len = data
+                    .iter()
+                    .position(|byte| *byte == 0xf7)
+                    .map_or(data.len(), |end| end + 1);
+                self.in_sysex = data[len - 1] != 0xf7;
+                self.send_long(&data[..len])?;
+                data = &data[len..];
+            } else if first >= 0x80 {
+                let len = message_len(first).min(data.len());
+                if first < 0xf0 {
+                    running_status = Some(first);
+                }
+                if first < 0xf8 {
+                    // any status but realtime ends a SysEx message
+                    self.in_sysex = false;
+                }
+                self.send_short(&data[..len])?;
+                data = &data[len..];
+            } else if let Some(status) = running_status {
+                // expand running status, as short messages always carry one
+                let len = (message_len(status) - 1).min(data.len());
+                let mut message = vec![status];
+                message.extend_from_slice(&data[..len]);
+                self.send_short(&message)?;
+                data = &data[len..];
+            } else {
+                warn!("Dropping data byte without status: {:#04x}", first);
+                data = &data[1..];
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WinMmOutput {
+    fn drop(&mut self) {
+        unsafe {
+            midiOutReset(self.handle);
+            let result = midiOutClose(self.handle);
+            if result != MMSYSERR_NOERROR {
+                warn!("midiOutClose failed: {}", result);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Loopback tests of the ALSA sequencer backend, one connection sending to a
+//! virtual destination of another, as a separate application would.
+//!
+//! They need the sequencer device `/dev/snd/seq`, so they are ignored by
+//! default, run them by `cargo test --test alsa -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use rmidi::builder::MidiConBuilder;
+use rmidi::midi_con::*;
+use rmidi::virtual_endpoint::VirtualDestination;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Received = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Sending connection, connected to the virtual destination of the receiving
+/// one, which is kept alive along with it.
+struct Loopback {
+    midi_con: ArcMutexMidiCon,
+    destination: EndpointId,
+    received: Received,
+    _receiver: (ArcMutexMidiCon, VirtualDestination),
+}
+
+fn loopback(name: &str) -> Loopback {
+    let client_name = format!("rmidi {} {}", name, std::process::id());
+    let receiver = MidiConBuilder::new()
+        .client_name(&client_name)
+        .build()
+        .unwrap();
+    let received = Received::default();
+    let log = received.clone();
+    let virtual_destination = receiver
+        .create_virtual_destination("In", move |_, data, _| {
+            log.lock().unwrap().push(data.to_vec())
+        })
+        .unwrap();
+
+    let midi_con = ArcMutexMidiCon::new().unwrap();
+    let port_name = format!("{}:In", client_name);
+    let (destination, _, _) = midi_con
+        .list_destinations()
+        .into_iter()
+        .find(|(_, _, name)| *name == port_name)
+        .expect("virtual destination not listed");
+    midi_con.connect_destination(destination).unwrap();
+    Loopback {
+        midi_con,
+        destination,
+        received,
+        _receiver: (receiver, virtual_destination),
+    }
+}
+
+/// Data received once `count` pieces arrived, or after a second.
+fn wait_for(received: &Received, count: usize) -> Vec<Vec<u8>> {
+    let start = Instant::now();
+    while received.lock().unwrap().len() < count && start.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    received.lock().unwrap().clone()
+}
+
+#[test]
+#[ignore = "needs the ALSA sequencer"]
+fn send() {
+    let lb = loopback("send");
+    lb.midi_con.send(lb.destination, &[0x90, 60, 100]).unwrap();
+    // SysEx split over two sends, with an interleaved clock tick
+    lb.midi_con
+        .send(lb.destination, &[0xf0, 0x7e, 0x7f])
+        .unwrap();
+    lb.midi_con
+        .send(lb.destination, &[0x06, 0xf8, 0x01, 0xf7, 0x80, 60, 0])
+        .unwrap();
+    assert_eq!(
+        wait_for(&lb.received, 4),
+        vec![
+            vec![0x90, 60, 100],
+            vec![0xf8],
+            vec![0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7],
+            vec![0x80, 60, 0],
+        ]
+    );
+}
+
+#[test]
+#[ignore = "needs the ALSA sequencer"]
+fn send_at_and_flush() {
+    let lb = loopback("schedule");
+    let now = lb.midi_con.now();
+    let at = |millis| now + Duration::from_millis(millis);
+    lb.midi_con
+        .send_at(lb.destination, at(150), &[0xfb])
+        .unwrap();
+    lb.midi_con
+        .send_at(lb.destination, at(100), &[0xfa])
+        .unwrap();
+    lb.midi_con
+        .send_at(lb.destination, at(200), &[0xf0, 0x7e, 0xf7])
+        .unwrap();
+    lb.midi_con
+        .send_at(lb.destination, at(500), &[0xfc])
+        .unwrap();
+    assert!(lb.received.lock().unwrap().is_empty());
+
+    thread::sleep(Duration::from_millis(300));
+    lb.midi_con.flush(lb.destination).unwrap();
+    thread::sleep(Duration::from_millis(400));
+    // in order of timestamp, the Stop being dropped
+    assert_eq!(
+        *lb.received.lock().unwrap(),
+        vec![vec![0xfa], vec![0xfb], vec![0xf0, 0x7e, 0xf7]]
+    );
+}
@@ -0,0 +1,56 @@
+use rmidi::builder::MidiConBuilder;
+use rmidi::channel::Delivery;
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+use rmidi::sysex::SysExLimits;
+
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn build() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let notifications = Arc::new(Mutex::new(vec![]));
+    let log = notifications.clone();
+    let midi_con = MidiConBuilder::new()
+        .client_name("Builder Test")
+        .backend(Box::new(mock.clone()))
+        .notification_callback(move |notification| log.lock().unwrap().push(notification.clone()))
+        .sysex_limits(SysExLimits {
+            max_size: 4,
+            timeout: None,
+        })
+        .delivery(Delivery::RingBuffer { capacity: 1 })
+        .build()
+        .unwrap();
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(keys.id(), false, "Keys".to_string())]
+    );
+
+    let mut receiver = midi_con.connect_source_channel(keys.id()).unwrap();
+    keys.inject(&[0xf0, 1, 2, 3, 0xf7]);
+    keys.inject(&[0x90, 60, 100]);
+    keys.inject(&[0x80, 60, 0]);
+    assert_eq!(receiver.try_recv().unwrap().data, vec![0x90, 60, 100]);
+    assert_eq!(receiver.try_recv(), None);
+    assert_eq!(receiver.dropped(), 1);
+
+    let pads = mock.add_source("Pads");
+    assert_eq!(
+        *notifications.lock().unwrap(),
+        vec![
+            Notification::InputError {
+                id: keys.id(),
+                error: MidiError::SysExTooLong(4)
+            },
+            Notification::SourceAdded {
+                id: pads.id(),
+                index: 1,
+                name: "Pads".to_string()
+            },
+            Notification::SetupChanged,
+        ]
+    );
+}
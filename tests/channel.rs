@@ -0,0 +1,101 @@
+use rmidi::channel::*;
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::time::Duration;
+
+#[test]
+fn channel() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let pads = mock.add_source("Pads");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let mut keys_receiver = midi_con.connect_source_channel(keys.id()).unwrap();
+    let (pads_id, mut pads_receiver) = midi_con.connect_source_by_name_channel("Pads").unwrap();
+    assert_eq!(pads_id, pads.id());
+    assert_eq!(
+        midi_con.connect_source_by_index_channel(0).err(),
+        Some(MidiError::AlreadyConnected(keys.id()))
+    );
+
+    keys.inject(&[0x90, 60, 100]);
+    mock.advance(Duration::from_millis(5));
+    pads.inject(&[0x99, 36, 127]);
+    keys.inject(&[0x80, 60, 0]);
+    assert_eq!(
+        keys_receiver.try_iter().collect::<Vec<_>>(),
+        vec![
+            MidiEvent {
+                source: keys.id(),
+                timestamp: Duration::ZERO,
+                data: vec![0x90, 60, 100].into()
+            },
+            MidiEvent {
+                source: keys.id(),
+                timestamp: Duration::from_millis(5),
+                data: vec![0x80, 60, 0].into()
+            },
+        ]
+    );
+    assert_eq!(
+        pads_receiver.try_recv(),
+        Some(MidiEvent {
+            source: pads.id(),
+            timestamp: Duration::from_millis(5),
+            data: vec![0x99, 36, 127].into()
+        })
+    );
+    assert_eq!(pads_receiver.try_recv(), None);
+    assert_eq!(keys_receiver.dropped(), 0);
+
+    // the source stays connected without its receiver
+    drop(keys_receiver);
+    keys.inject(&[0x90, 60, 100]);
+    assert_eq!(keys.connections(), 1);
+}
+
+#[test]
+fn ring_buffer() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.set_delivery(Delivery::RingBuffer { capacity: 2 });
+
+    let mut receiver = midi_con.connect_source_channel(keys.id()).unwrap();
+    for note in 60..64 {
+        keys.inject(&[0x90, note, 100]);
+    }
+    assert_eq!(receiver.dropped(), 2);
+    assert_eq!(
+        receiver
+            .try_iter()
+            .map(|event| event.data)
+            .collect::<Vec<_>>(),
+        vec![vec![0x90, 60, 100], vec![0x90, 61, 100]]
+    );
+
+    keys.inject(&[0x90, 64, 100]);
+    assert_eq!(receiver.try_recv().unwrap().data, vec![0x90, 64, 100]);
+    assert_eq!(receiver.dropped(), 2);
+
+    // SysEx does not fit inline, but passes all the same
+    keys.inject(&[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]);
+    assert_eq!(
+        receiver.try_recv().unwrap().data,
+        vec![0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]
+    );
+}
+
+#[test]
+fn midi_data() {
+    let short = MidiData::from(&[0xc0, 5][..]);
+    assert_eq!(&*short, &[0xc0, 5]);
+    assert_eq!(short, MidiData::from(vec![0xc0, 5]));
+    assert_eq!(format!("{:?}", short), "[192, 5]");
+    let long = MidiData::from(vec![0x90, 60, 100, 62, 100]);
+    assert_eq!(long.len(), 5);
+    assert_eq!(long, MidiData::from(&[0x90, 60, 100, 62, 100][..]));
+    assert_ne!(long, short);
+}
@@ -0,0 +1,261 @@
+mod common;
+
+use rmidi::clock::{ClockEvent, ClockFollower, ClockMaster};
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::{MockBackend, MockDestination};
+
+use common::*;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Clock master at 125 bpm, i.e., a tick every 20 ms, sending to two
+/// destinations, at 1 s.
+fn setup() -> (MockBackend, [MockDestination; 2], ClockMaster) {
+    let (mock, midi_con, [drums, pedal]) = destinations(["Drums", "Pedal"]);
+    let clock = ClockMaster::new(&midi_con, &[drums.id(), pedal.id()], 125.0).unwrap();
+    (mock, [drums, pedal], clock)
+}
+
+/// Ticks at the given milliseconds.
+fn ticks(millis: impl IntoIterator<Item = u64>) -> Vec<(Duration, Vec<u8>)> {
+    millis
+        .into_iter()
+        .map(|millis| (ms(millis), vec![0xf8]))
+        .collect()
+}
+
+#[test]
+fn transport() {
+    let (mock, [drums, pedal], mut clock) = setup();
+    assert!(!clock.is_running());
+    clock.start().unwrap();
+    run(&mock, ms(100), || {
+        clock.update().unwrap();
+    });
+    assert_eq!(clock.position(), 5);
+    clock.stop().unwrap();
+    assert_eq!(clock.position(), 11);
+    let started = [
+        vec![(ms(1000), vec![0xfa])],
+        ticks((0..11).map(|tick| 1000 + tick * 20)),
+        // at the tick following the ones scheduled
+        vec![(ms(1220), vec![0xfc])],
+    ]
+    .concat();
+    assert_eq!(drums.take_sent_at(), started);
+    assert_eq!(pedal.take_sent_at(), started);
+
+    mock.advance(ms(400));
+    clock.resume().unwrap();
+    assert!(clock.is_running());
+    assert_eq!(clock.position(), 11);
+    // the Song Position Pointer of the fifth quarter note
+    clock.locate(16).unwrap();
+    run(&mock, ms(50), || {
+        clock.update().unwrap();
+    });
+    clock.remove_destination(clock.destinations()[1]);
+    run(&mock, ms(150), || {
+        clock.update().unwrap();
+    });
+    assert_eq!(clock.position(), 96 + 4);
+    assert_eq!(
+        drums.take_sent_at(),
+        [
+            vec![(ms(1500), vec![0xfb])],
+            ticks((0..6).map(|tick| 1500 + tick * 20)),
+            vec![
+                (ms(1620), vec![0xfc]),
+                (ms(1620), vec![0xf2, 16, 0]),
+                (ms(1620), vec![0xfb]),
+            ],
+            ticks((0..10).map(|tick| 1620 + tick * 20)),
+        ]
+        .concat()
+    );
+    assert_eq!(pedal.sent_at().len(), 12);
+
+    assert_eq!(
+        clock.locate(0x4000),
+        Err(MidiError::InvalidMessage {
+            field: "song position",
+            value: 0x4000
+        })
+    );
+    clock.stop().unwrap();
+    clock.locate(0x3fff).unwrap();
+    assert_eq!(clock.position(), 0x3fff * 6);
+    assert_eq!(
+        drums.take_sent_at(),
+        // not before the Stop scheduled
+        vec![(ms(1820), vec![0xfc]), (ms(1820), vec![0xf2, 0x7f, 0x7f])]
+    );
+}
+
+#[test]
+fn tempo_and_swing() {
+    let (mock, [drums, _], mut clock) = setup();
+    assert_eq!(clock.tempo(), 125.0);
+    // 180 ms and 60 ms sixteenths
+    clock.set_swing(75.0).unwrap();
+    clock.start().unwrap();
+    // 90 ms and 30 ms sixteenths from the next tick on, at 1.12 s
+    clock.set_tempo(250.0).unwrap();
+    run(&mock, ms(100), || {
+        clock.update().unwrap();
+    });
+    assert_eq!(clock.position(), 3);
+    clock.set_swing(50.0).unwrap();
+    run(&mock, ms(100), || {
+        clock.update().unwrap();
+    });
+    assert_eq!(
+        drums.take_sent_at(),
+        [
+            vec![(ms(1000), vec![0xfa])],
+            ticks([1000, 1030, 1060, 1090]),
+            ticks([1120, 1135, 1150]),
+            ticks([1155, 1160, 1165, 1170, 1175, 1180, 1195]),
+            // straight, 10 ms per tick
+            ticks((0..10).map(|tick| 1210 + tick * 10)),
+        ]
+        .concat()
+    );
+    assert_eq!(clock.position(), 13);
+
+    assert_eq!(
+        clock.set_tempo(0.5),
+        Err(MidiError::InvalidSetting {
+            field: "tempo",
+            value: "0.5".to_string()
+        })
+    );
+    assert_eq!(
+        clock.set_swing(75.5),
+        Err(MidiError::InvalidSetting {
+            field: "swing",
+            value: "75.5".to_string()
+        })
+    );
+}
+
+#[test]
+fn follower() {
+    let mock = MockBackend::new();
+    let sync = mock.add_source("Sync");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let events = Arc::new(Mutex::new(vec![]));
+    let follower = {
+        let events = events.clone();
+        ClockFollower::new(move |event| events.lock().unwrap().push(event.clone()))
+    };
+    assert_eq!(
+        midi_con.connect_source_by_name_clock_follower("Sync", &follower),
+        Ok(sync.id())
+    );
+    let take = || std::mem::take(&mut *events.lock().unwrap());
+
+    // 20 ms ticks, i.e., 125 bpm, with a jitter of 1 ms
+    sync.inject(&[0xfa]);
+    for tick in 0..24 {
+        mock.advance(ms(if tick % 2 == 0 { 21 } else { 19 }));
+        sync.inject(&[0xf8]);
+    }
+    // realtime in the middle of a note on
+    mock.advance(ms(21));
+    sync.inject(&[0x90, 60, 0xf8, 100]);
+    assert_eq!(
+        take(),
+        // without reporting the jitter
+        vec![ClockEvent::Start, ClockEvent::Tempo(125.0)]
+    );
+    assert!(follower.is_running());
+    assert_eq!(follower.position(), 1.0);
+    assert_eq!(follower.tempo(), Some(125.0));
+
+    sync.inject(&[0xfc, 0xf2, 12, 0]);
+    mock.advance(ms(20));
+    sync.inject(&[0xf8]);
+    assert!(!follower.is_running());
+    assert_eq!(follower.position(), 3.0);
+    // twice the tempo from now on, reported as the estimate follows
+    sync.inject(&[0xfb]);
+    for _ in 0..24 {
+        mock.advance(ms(10));
+        sync.inject(&[0xf8]);
+    }
+    // the first tick after Continue at the song position
+    assert_eq!(follower.position(), 3.0 + 23.0 / 24.0);
+    let events = take();
+    assert_eq!(
+        events[..3],
+        [
+            ClockEvent::Stop,
+            ClockEvent::SongPosition(12),
+            ClockEvent::Continue
+        ]
+    );
+    assert_eq!(events.last(), Some(&ClockEvent::Tempo(250.0)));
+    assert!(events[3..].windows(2).all(|pair| match pair {
+        [ClockEvent::Tempo(previous), ClockEvent::Tempo(tempo)] => tempo - previous >= 1.0,
+        _ => false,
+    }));
+
+    // dropout while waiting, reported once
+    mock.advance(ms(250));
+    follower.check(midi_con.now());
+    mock.advance(ms(10));
+    follower.check(midi_con.now());
+    assert_eq!(follower.tempo(), None);
+    assert_eq!(take(), vec![ClockEvent::Dropout]);
+    // resuming, and dropping out again before a check
+    for _ in 0..7 {
+        mock.advance(ms(10));
+        sync.inject(&[0xf8]);
+    }
+    mock.advance(ms(300));
+    sync.inject(&[0xf8]);
+    assert_eq!(take(), vec![ClockEvent::Tempo(250.0), ClockEvent::Dropout]);
+    assert!(follower.is_running());
+    assert_eq!(follower.position(), 3.0 + 31.0 / 24.0);
+}
+
+#[test]
+fn follower_watchdog() {
+    let mock = MockBackend::new();
+    let sync = mock.add_source("Sync");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let events = Arc::new(Mutex::new(vec![]));
+    let follower = {
+        let events = events.clone();
+        ClockFollower::new(move |event| events.lock().unwrap().push(event.clone()))
+    };
+    midi_con
+        .connect_source_clock_follower(sync.id(), &follower)
+        .unwrap();
+
+    sync.inject(&[0xfa]);
+    for _ in 0..12 {
+        mock.advance(ms(20));
+        sync.inject(&[0xf8]);
+    }
+    // the clock just stops, reported without a check by the application
+    mock.advance(ms(300));
+    for _ in 0..40 {
+        if events.lock().unwrap().contains(&ClockEvent::Dropout) {
+            break;
+        }
+        std::thread::sleep(ms(50));
+    }
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            ClockEvent::Start,
+            ClockEvent::Tempo(125.0),
+            ClockEvent::Dropout
+        ]
+    );
+    assert_eq!(follower.tempo(), None);
+}
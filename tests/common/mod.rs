@@ -0,0 +1,44 @@
+//! Helpers shared by the integration tests, each using a part of them.
+#![allow(dead_code)]
+
+use rmidi::midi_con::ArcMutexMidiCon;
+use rmidi::mock::{MockBackend, MockDestination};
+use rmidi::smf::{Event, MetaEvent, TrackEvent};
+
+use std::time::Duration;
+
+pub fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+pub fn event(delta: u32, event: Event) -> TrackEvent {
+    TrackEvent { delta, event }
+}
+
+pub fn end(delta: u32) -> TrackEvent {
+    event(delta, Event::Meta(MetaEvent::EndOfTrack))
+}
+
+/// Mock backend with destinations of the given names connected by index,
+/// advanced to 1 s.
+pub fn destinations<const N: usize>(
+    names: [&str; N],
+) -> (MockBackend, ArcMutexMidiCon, [MockDestination; N]) {
+    let mock = MockBackend::new();
+    let destinations = names.map(|name| mock.add_destination(name));
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    for index in 0..N {
+        midi_con.connect_destination_by_index(index).unwrap();
+    }
+    mock.advance(Duration::from_secs(1));
+    (mock, midi_con, destinations)
+}
+
+/// Advance `mock` by 50 ms at a time for `duration`, calling `update` after
+/// each step.
+pub fn run(mock: &MockBackend, duration: Duration, mut update: impl FnMut()) {
+    for _ in 0..duration.as_millis() / 50 {
+        mock.advance(ms(50));
+        update();
+    }
+}
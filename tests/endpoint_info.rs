@@ -0,0 +1,41 @@
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+#[test]
+fn list_info() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    keys.set_device("Acme", "K-49", "Keystation");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_source(keys.id(), |_, _, _| {}).unwrap();
+    let echo = midi_con.create_virtual_source("Echo").unwrap();
+
+    assert_eq!(
+        midi_con.list_source_info(),
+        vec![
+            EndpointInfo {
+                display_name: "Keystation Keys".to_string(),
+                manufacturer: Some("Acme".to_string()),
+                model: Some("K-49".to_string()),
+                device: Some("Keystation".to_string()),
+                connected: true,
+                ..EndpointInfo::new(keys.id(), "Keys".to_string())
+            },
+            EndpointInfo {
+                is_virtual: true,
+                ..EndpointInfo::new(echo.id(), "Echo".to_string())
+            },
+        ]
+    );
+    assert_eq!(
+        midi_con.list_destination_info(),
+        vec![EndpointInfo::new(synth.id(), "Synth".to_string())]
+    );
+
+    // the flags follow the connections
+    midi_con.connect_destination(synth.id()).unwrap();
+    midi_con.disconnect_source(keys.id()).unwrap();
+    assert!(!midi_con.list_source_info()[0].connected);
+    assert!(midi_con.list_destination_info()[0].connected);
+}
@@ -0,0 +1,265 @@
+use rmidi::error::MidiError;
+use rmidi::message::*;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn parse(parser: &mut MidiParser, data: &[u8]) -> Vec<MidiMessage> {
+    let mut messages = vec![];
+    parser.parse(data, |message| messages.push(message));
+    messages
+}
+
+#[test]
+fn running_status() {
+    let mut parser = MidiParser::new();
+    assert_eq!(
+        parse(&mut parser, &[0xb1, 7, 100, 10]),
+        vec![MidiMessage::ControlChange {
+            channel: 1,
+            controller: 7,
+            value: 100
+        }]
+    );
+    // continued in the next packet
+    assert_eq!(
+        parse(&mut parser, &[64, 0xe1, 0x00, 0x40, 0x7f, 0x7f]),
+        vec![
+            MidiMessage::ControlChange {
+                channel: 1,
+                controller: 10,
+                value: 64
+            },
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: 0x2000
+            },
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: 0x3fff
+            },
+        ]
+    );
+    // system common messages cancel running status
+    assert_eq!(
+        parse(&mut parser, &[0xc2, 5, 6, 0xf3, 1, 2]),
+        vec![
+            MidiMessage::ProgramChange {
+                channel: 2,
+                program: 5
+            },
+            MidiMessage::ProgramChange {
+                channel: 2,
+                program: 6
+            },
+            MidiMessage::SongSelect(1),
+        ]
+    );
+    assert_eq!(
+        parse(&mut parser, &[0xf2, 0x10, 0x01, 0xf6]),
+        vec![MidiMessage::SongPosition(0x90), MidiMessage::TuneRequest]
+    );
+}
+
+#[test]
+fn sysex() {
+    let mut parser = MidiParser::new();
+    assert_eq!(parse(&mut parser, &[0xf0, 0x7e, 0x7f]), vec![]);
+    assert_eq!(
+        parse(&mut parser, &[0x06, 0xf8, 0x01, 0xf7, 0xfe]),
+        vec![
+            MidiMessage::TimingClock,
+            MidiMessage::SysEx(vec![0x7e, 0x7f, 0x06, 0x01]),
+            MidiMessage::ActiveSensing,
+        ]
+    );
+    // interrupted by a note on
+    assert_eq!(
+        parse(&mut parser, &[0xf0, 0x43, 0x90, 60, 100, 0xf7]),
+        vec![MidiMessage::NoteOn {
+            channel: 0,
+            note: 60,
+            velocity: 100
+        }]
+    );
+    // undefined status bytes and stray data bytes are dropped
+    assert_eq!(
+        parse(&mut parser, &[0xf4, 1, 0xf9, 0xfa]),
+        vec![MidiMessage::Start]
+    );
+}
+
+#[test]
+fn connect_source_messages() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    midi_con
+        .connect_source_messages(keys.id(), move |timestamp, message, _| {
+            log.lock().unwrap().push((timestamp, message.clone()))
+        })
+        .unwrap();
+    mock.advance(Duration::from_millis(10));
+    keys.inject(&[0x93, 60]);
+    mock.advance(Duration::from_millis(10));
+    keys.inject(&[100, 60, 0]);
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![
+            (
+                Duration::from_millis(20),
+                MidiMessage::NoteOn {
+                    channel: 3,
+                    note: 60,
+                    velocity: 100
+                }
+            ),
+            (
+                Duration::from_millis(20),
+                MidiMessage::NoteOn {
+                    channel: 3,
+                    note: 60,
+                    velocity: 0
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn encode() {
+    let messages = vec![
+        MidiMessage::note_on(1, 60, 100).unwrap(),
+        MidiMessage::note_off(16, 60, 0).unwrap(),
+        MidiMessage::poly_aftertouch(2, 60, 10).unwrap(),
+        MidiMessage::control_change(2, 7, 127).unwrap(),
+        MidiMessage::program_change(3, 5).unwrap(),
+        MidiMessage::channel_aftertouch(3, 20).unwrap(),
+        MidiMessage::pitch_bend(4, 0x2000).unwrap(),
+        MidiMessage::SysEx(vec![0x7e, 0x7f, 0x06, 0x01]),
+        MidiMessage::MtcQuarterFrame(0x12),
+        MidiMessage::SongPosition(0x90),
+        MidiMessage::SongSelect(1),
+        MidiMessage::TuneRequest,
+        MidiMessage::TimingClock,
+        MidiMessage::Start,
+        MidiMessage::Continue,
+        MidiMessage::Stop,
+        MidiMessage::ActiveSensing,
+        MidiMessage::SystemReset,
+    ];
+    let mut data = vec![];
+    let mut encoder = MidiEncoder::new(false);
+    for message in &messages {
+        encoder.encode(message, &mut data).unwrap();
+    }
+    assert_eq!(&data[..3], &[0x90, 60, 100]);
+    assert_eq!(messages[1].channel_number(), Some(16));
+    assert_eq!(messages[1].to_bytes().unwrap(), vec![0x8f, 60, 0]);
+    assert_eq!(messages[6].to_bytes().unwrap(), vec![0xe3, 0x00, 0x40]);
+    assert_eq!(parse(&mut MidiParser::new(), &data), messages);
+}
+
+#[test]
+fn invalid_messages() {
+    assert_eq!(
+        MidiMessage::note_on(0, 60, 100),
+        Err(MidiError::InvalidMessage {
+            field: "channel",
+            value: 0
+        })
+    );
+    assert_eq!(
+        MidiMessage::program_change(17, 0),
+        Err(MidiError::InvalidMessage {
+            field: "channel",
+            value: 17
+        })
+    );
+    assert_eq!(
+        MidiMessage::control_change(1, 7, 128),
+        Err(MidiError::InvalidMessage {
+            field: "value",
+            value: 128
+        })
+    );
+    assert_eq!(
+        MidiMessage::pitch_bend(1, 0x4000),
+        Err(MidiError::InvalidMessage {
+            field: "pitch bend",
+            value: 0x4000
+        })
+    );
+    assert_eq!(
+        MidiMessage::SysEx(vec![0x7e, 0xf7]).to_bytes(),
+        Err(MidiError::InvalidMessage {
+            field: "SysEx data",
+            value: 0xf7
+        })
+    );
+    assert_eq!(
+        MidiMessage::ProgramChange {
+            channel: 16,
+            program: 0
+        }
+        .to_bytes(),
+        Err(MidiError::InvalidMessage {
+            field: "channel",
+            value: 16
+        })
+    );
+}
+
+#[test]
+fn send_messages() {
+    let mock = MockBackend::new();
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let messages = [
+        MidiMessage::note_on(1, 60, 100).unwrap(),
+        MidiMessage::TimingClock,
+        MidiMessage::note_on(1, 64, 100).unwrap(),
+        MidiMessage::SongSelect(1),
+        MidiMessage::note_on(1, 60, 0).unwrap(),
+    ];
+
+    assert_eq!(
+        midi_con.send_message(synth.id(), &messages[0]),
+        Err(MidiError::NotConnected(synth.id()))
+    );
+    midi_con.connect_destination(synth.id()).unwrap();
+    midi_con.send_message(synth.id(), &messages[0]).unwrap();
+    midi_con.send_messages(synth.id(), &messages).unwrap();
+    midi_con.set_running_status(synth.id(), true).unwrap();
+    midi_con.send_messages(synth.id(), &messages).unwrap();
+    assert_eq!(
+        synth.take_sent(),
+        vec![
+            vec![0x90, 60, 100],
+            vec![0x90, 60, 100, 0xf8, 0x90, 64, 100, 0xf3, 1, 0x90, 60, 0],
+            vec![0x90, 60, 100, 0xf8, 64, 100, 0xf3, 1, 0x90, 60, 0],
+        ]
+    );
+
+    // nothing is sent if any message is invalid
+    assert!(
+        midi_con
+            .send_messages(
+                synth.id(),
+                &[
+                    messages[0].clone(),
+                    MidiMessage::ProgramChange {
+                        channel: 0,
+                        program: 128
+                    }
+                ]
+            )
+            .is_err()
+    );
+    assert!(synth.sent().is_empty());
+}
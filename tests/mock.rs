@@ -0,0 +1,364 @@
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn inject_and_send() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(keys.id(), false, "Keys".to_string())]
+    );
+    assert_eq!(
+        midi_con.list_destinations(),
+        vec![(synth.id(), false, "Synth".to_string())]
+    );
+
+    let synth_id = midi_con.connect_destination_by_index(0).unwrap();
+    assert_eq!(synth_id, synth.id());
+    midi_con
+        .connect_source(keys.id(), move |_, data, mc| {
+            mc.send(synth_id, data).unwrap()
+        })
+        .unwrap();
+    assert_eq!(keys.connections(), 1);
+
+    keys.inject(&[0x90, 60, 100]);
+    keys.inject(&[0x80, 60, 0]);
+    assert_eq!(
+        synth.take_sent(),
+        vec![vec![0x90, 60, 100], vec![0x80, 60, 0]]
+    );
+
+    midi_con.disconnect_source(keys.id()).unwrap();
+    assert_eq!(keys.connections(), 0);
+    keys.inject(&[0x90, 60, 100]);
+    assert!(synth.sent().is_empty());
+}
+
+#[test]
+fn hot_plug() {
+    let mock = MockBackend::new();
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let notifications = Arc::new(Mutex::new(vec![]));
+    let log = notifications.clone();
+    midi_con.set_notification_callback(move |notification| {
+        log.lock().unwrap().push(notification.clone())
+    });
+
+    let keys = mock.add_source("Keys");
+    let pads = mock.add_source("Pads");
+    let synth = mock.add_destination("Synth");
+    assert_eq!(midi_con.list_sources().len(), 2);
+
+    keys.unplug();
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(pads.id(), false, "Pads".to_string())]
+    );
+
+    keys.plug();
+    assert_eq!(midi_con.list_sources().len(), 2);
+
+    assert_eq!(
+        *notifications.lock().unwrap(),
+        vec![
+            Notification::SourceAdded {
+                id: keys.id(),
+                index: 0,
+                name: "Keys".to_string()
+            },
+            Notification::SetupChanged,
+            Notification::SourceAdded {
+                id: pads.id(),
+                index: 1,
+                name: "Pads".to_string()
+            },
+            Notification::SetupChanged,
+            Notification::DestinationAdded {
+                id: synth.id(),
+                index: 0,
+                name: "Synth".to_string()
+            },
+            Notification::SetupChanged,
+            Notification::SourceRemoved {
+                id: keys.id(),
+                index: 0,
+                name: "Keys".to_string()
+            },
+            Notification::SetupChanged,
+            Notification::SourceAdded {
+                id: keys.id(),
+                index: 0,
+                name: "Keys".to_string()
+            },
+            Notification::SetupChanged,
+        ]
+    );
+}
+
+#[test]
+fn ids_survive_re_enumeration() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let pads = mock.add_source("Pads");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    let pads_id = midi_con
+        .connect_source_by_index(1, move |_, data, _| log.lock().unwrap().push(data.to_vec()))
+        .unwrap();
+    assert_eq!(pads_id, pads.id());
+
+    // pads moves to index 0, the connection follows it
+    keys.unplug();
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(pads.id(), true, "Pads".to_string())]
+    );
+    pads.inject(&[0xf8]);
+    assert_eq!(*received.lock().unwrap(), vec![vec![0xf8]]);
+    midi_con.disconnect_source(pads_id).unwrap();
+    assert_eq!(pads.connections(), 0);
+}
+
+#[test]
+fn notification_callback_may_list_endpoints() {
+    let mock = MockBackend::new();
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let sources = Arc::new(Mutex::new(vec![]));
+    let cb_sources = sources.clone();
+    let mc = midi_con.clone();
+    midi_con.set_notification_callback(move |notification| {
+        if let Notification::SourceAdded { .. } | Notification::SourceRemoved { .. } = notification
+        {
+            *cb_sources.lock().unwrap() = mc.list_sources();
+        }
+    });
+
+    let keys = mock.add_source("Keys");
+    assert_eq!(
+        *sources.lock().unwrap(),
+        vec![(keys.id(), false, "Keys".to_string())]
+    );
+    keys.unplug();
+    assert!(sources.lock().unwrap().is_empty());
+}
+
+#[test]
+fn errors() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    assert_eq!(
+        midi_con.connect_source_by_index(1, |_, _, _| {}),
+        Err(MidiError::UnknownIndex(1))
+    );
+    assert_eq!(
+        midi_con.connect_source_by_name("Pads", |_, _, _| {}),
+        Err(MidiError::UnknownName("Pads".to_string()))
+    );
+    assert_eq!(
+        midi_con.connect_source(synth.id(), |_, _, _| {}),
+        Err(MidiError::UnknownEndpoint(synth.id()))
+    );
+    midi_con
+        .connect_source_by_name("Keys", |_, _, _| {})
+        .unwrap();
+    assert_eq!(
+        midi_con.connect_source_by_index(0, |_, _, _| {}),
+        Err(MidiError::AlreadyConnected(keys.id()))
+    );
+    assert_eq!(
+        midi_con.disconnect_source(synth.id()),
+        Err(MidiError::NotConnected(synth.id()))
+    );
+
+    assert_eq!(
+        midi_con.send(synth.id(), &[0xfe]),
+        Err(MidiError::NotConnected(synth.id()))
+    );
+}
+
+#[test]
+fn reconnect() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let notifications = Arc::new(Mutex::new(vec![]));
+    let log = notifications.clone();
+    midi_con.set_notification_callback(move |notification| {
+        if let Notification::SourceReconnected { .. }
+        | Notification::DestinationReconnected { .. } = notification
+        {
+            log.lock().unwrap().push(notification.clone())
+        }
+    });
+
+    let synth_id = synth.id();
+    midi_con.connect_destination(synth_id).unwrap();
+    midi_con
+        .connect_source(keys.id(), move |_, data, mc| {
+            mc.send(synth_id, data).unwrap()
+        })
+        .unwrap();
+
+    // detached, sending is still allowed but nothing arrives
+    keys.unplug();
+    synth.unplug();
+    assert_eq!(keys.connections(), 0);
+    assert_eq!(midi_con.send(synth.id(), &[0xfe]), Ok(()));
+
+    // re-attached under the same id
+    keys.plug();
+    synth.plug();
+    assert_eq!(keys.connections(), 1);
+    keys.inject(&[0x90, 60, 100]);
+    assert_eq!(synth.take_sent(), vec![vec![0x90, 60, 100]]);
+
+    // re-attached under another id, matched by name
+    keys.unplug();
+    let new_keys = mock.add_source("Keys");
+    assert_eq!(new_keys.connections(), 1);
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(new_keys.id(), true, "Keys".to_string())]
+    );
+    new_keys.inject(&[0x80, 60, 0]);
+    assert_eq!(synth.take_sent(), vec![vec![0x80, 60, 0]]);
+
+    // the original id still refers to the connection
+    midi_con.disconnect_source(keys.id()).unwrap();
+    assert_eq!(new_keys.connections(), 0);
+
+    assert_eq!(
+        *notifications.lock().unwrap(),
+        vec![
+            Notification::SourceReconnected {
+                id: keys.id(),
+                index: 0,
+                name: "Keys".to_string()
+            },
+            Notification::DestinationReconnected {
+                id: synth.id(),
+                index: 0,
+                name: "Synth".to_string()
+            },
+            Notification::SourceReconnected {
+                id: keys.id(),
+                index: 0,
+                name: "Keys".to_string()
+            },
+        ]
+    );
+
+    // not matched by name to a source connected already
+    midi_con
+        .connect_source(new_keys.id(), |_, _, _| {})
+        .unwrap();
+    let other_keys = mock.add_source("Keys");
+    midi_con
+        .connect_source(other_keys.id(), |_, _, _| {})
+        .unwrap();
+    other_keys.unplug();
+    let third_keys = mock.add_source("Keys");
+    assert_eq!(new_keys.connections(), 1);
+    assert_eq!(third_keys.connections(), 1);
+}
+
+#[test]
+fn dropped_with_connections() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_source(keys.id(), |_, _, _| {}).unwrap();
+    midi_con.connect_destination(synth.id()).unwrap();
+
+    // neither the backend nor the source hold on to the connection
+    let weak = Arc::downgrade(&midi_con.0);
+    drop(midi_con);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(keys.connections(), 0);
+    keys.inject(&[0x90, 60, 100]);
+}
+
+#[test]
+fn timestamps() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    assert_eq!(midi_con.now(), Duration::ZERO);
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    midi_con
+        .connect_source(keys.id(), move |timestamp, data, _| {
+            log.lock().unwrap().push((timestamp, data.to_vec()))
+        })
+        .unwrap();
+    keys.inject(&[0xfa]);
+    mock.advance(Duration::from_millis(500));
+    assert_eq!(midi_con.now(), Duration::from_millis(500));
+    keys.inject(&[0xf8]);
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![
+            (Duration::ZERO, vec![0xfa]),
+            (Duration::from_millis(500), vec![0xf8])
+        ]
+    );
+}
+
+#[test]
+fn send_at() {
+    let mock = MockBackend::new();
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_destination(synth.id()).unwrap();
+
+    mock.advance(Duration::from_secs(1));
+    midi_con.send(synth.id(), &[0xfa]).unwrap();
+    midi_con
+        .send_at(synth.id(), Duration::from_millis(1500), &[0xf8])
+        .unwrap();
+    midi_con
+        .send_at(synth.id(), Duration::from_millis(2000), &[0xfc])
+        .unwrap();
+    // in the past, delivered right away
+    midi_con
+        .send_at(synth.id(), Duration::from_millis(500), &[0xfe])
+        .unwrap();
+    mock.advance(Duration::from_secs(1));
+    midi_con.flush(synth.id()).unwrap();
+    midi_con
+        .send_at(synth.id(), Duration::from_millis(2500), &[0xfb])
+        .unwrap();
+    midi_con.disconnect_destination(synth.id()).unwrap();
+    assert_eq!(
+        midi_con.flush(synth.id()),
+        Err(MidiError::NotConnected(synth.id()))
+    );
+
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (Duration::from_millis(1000), vec![0xfa]),
+            (Duration::from_millis(1500), vec![0xf8]),
+            (Duration::from_millis(2000), vec![0xfc]),
+            (Duration::from_millis(1000), vec![0xfe]),
+            (Duration::from_millis(2500), vec![0xfb]),
+        ]
+    );
+}
@@ -0,0 +1,352 @@
+mod common;
+
+use rmidi::error::MidiError;
+use rmidi::message::MidiMessage;
+use rmidi::midi_con::*;
+use rmidi::mock::{MockBackend, MockDestination};
+use rmidi::player::Player;
+use rmidi::smf::*;
+
+use common::*;
+
+use std::time::Duration;
+
+fn note(delta: u32, channel: u8, note: u8, velocity: u8) -> TrackEvent {
+    event(
+        delta,
+        Event::Midi(MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity,
+        }),
+    )
+}
+
+/// Player of `smf` at 1 s, with a destination connected by index.
+fn setup(smf: &Smf) -> (MockBackend, MockDestination, Player) {
+    let (mock, midi_con, [synth]) = destinations(["Synth"]);
+    let player = Player::new(&midi_con, synth.id(), smf).unwrap();
+    (mock, synth, player)
+}
+
+/// 480 ticks per quarter note at 120 bpm, doubled to 240 bpm at 1 s, lasting
+/// 1.5 s.
+fn song() -> Smf {
+    Smf {
+        format: Format::Simultaneous,
+        timing: Timing::Metrical(480),
+        tracks: vec![
+            Track {
+                events: vec![event(960, Event::Meta(MetaEvent::Tempo(250_000))), end(960)],
+            },
+            Track {
+                events: vec![
+                    note(0, 0, 60, 100),
+                    note(480, 0, 60, 0),
+                    note(480, 0, 62, 100),
+                    note(480, 0, 62, 0),
+                    end(0),
+                ],
+            },
+            Track {
+                events: vec![
+                    note(240, 9, 36, 100),
+                    event(0, Event::SysEx(vec![0x7e, 0x7f, 0x09, 0x01, 0xf7])),
+                    note(240, 9, 36, 0),
+                    end(0),
+                ],
+            },
+        ],
+    }
+}
+
+#[test]
+fn play() {
+    let smf = song();
+    let (mock, synth, mut player) = setup(&smf);
+    assert_eq!(player.duration(), ms(1500));
+    assert!(!player.is_playing());
+
+    player.play().unwrap();
+    assert!(player.is_playing());
+    // scheduled ahead of time
+    assert_eq!(synth.sent_at(), vec![(ms(1000), vec![0x90, 60, 100])]);
+    run(&mock, ms(1400), || {
+        player.update().unwrap();
+    });
+    assert_eq!(player.position(), ms(1400));
+    assert!(player.is_playing());
+    run(&mock, ms(100), || {
+        player.update().unwrap();
+    });
+    assert!(!player.is_playing());
+    assert_eq!(player.position(), ms(1500));
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (ms(1000), vec![0x90, 60, 100]),
+            (ms(1250), vec![0x99, 36, 100]),
+            (ms(1250), vec![0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]),
+            (ms(1500), vec![0x90, 60, 0]),
+            (ms(1500), vec![0x99, 36, 0]),
+            (ms(2000), vec![0x90, 62, 100]),
+            (ms(2250), vec![0x90, 62, 0]),
+        ]
+    );
+}
+
+#[test]
+fn pause_and_stop() {
+    let smf = song();
+    let (mock, synth, mut player) = setup(&smf);
+    player.play().unwrap();
+    run(&mock, ms(300), || {
+        player.update().unwrap();
+    });
+    // the note off of the SysEx track at 1.5 s is dropped
+    player.pause().unwrap();
+    assert_eq!(player.position(), ms(300));
+    run(&mock, ms(700), || {
+        player.update().unwrap();
+    });
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (ms(1000), vec![0x90, 60, 100]),
+            (ms(1250), vec![0x99, 36, 100]),
+            (ms(1250), vec![0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]),
+            (ms(1300), vec![0x80, 60, 0]),
+            (ms(1300), vec![0x89, 36, 0]),
+            (ms(1300), vec![0xb0, 64, 0]),
+            (ms(1300), vec![0xb0, 123, 0]),
+            (ms(1300), vec![0xb9, 64, 0]),
+            (ms(1300), vec![0xb9, 123, 0]),
+        ]
+    );
+
+    // resume at 2 s
+    player.play().unwrap();
+    run(&mock, ms(200), || {
+        player.update().unwrap();
+    });
+    player.stop().unwrap();
+    assert_eq!(player.position(), Duration::ZERO);
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (ms(2200), vec![0x90, 60, 0]),
+            (ms(2200), vec![0x99, 36, 0]),
+            (ms(2200), vec![0xb0, 64, 0]),
+            (ms(2200), vec![0xb0, 123, 0]),
+            (ms(2200), vec![0xb9, 64, 0]),
+            (ms(2200), vec![0xb9, 123, 0]),
+        ]
+    );
+}
+
+#[test]
+fn seek_and_tempo_scale() {
+    let smf = song();
+    let (mock, synth, mut player) = setup(&smf);
+    player.seek(ms(1100)).unwrap();
+    assert_eq!(player.position(), ms(1100));
+    player.set_tempo_scale(2.0).unwrap();
+    player.play().unwrap();
+    run(&mock, ms(200), || {
+        player.update().unwrap();
+    });
+    assert!(!player.is_playing());
+    assert_eq!(synth.take_sent_at(), vec![(ms(1075), vec![0x90, 62, 0])]);
+
+    // seeking while playing silences the notes on, with their note offs
+    // dropped
+    player.seek(Duration::ZERO).unwrap();
+    player.play().unwrap();
+    run(&mock, ms(200), || {
+        player.update().unwrap();
+    });
+    player.seek(ms(900)).unwrap();
+    run(&mock, ms(100), || {
+        player.update().unwrap();
+    });
+    // back to the tempo of the file at 1.5 s, from 1.1 s of the file
+    player.set_tempo_scale(1.0).unwrap();
+    run(&mock, ms(400), || {
+        player.update().unwrap();
+    });
+    assert!(!player.is_playing());
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (ms(1200), vec![0x90, 60, 100]),
+            (ms(1325), vec![0x99, 36, 100]),
+            (ms(1325), vec![0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]),
+            (ms(1400), vec![0x80, 60, 0]),
+            (ms(1400), vec![0x89, 36, 0]),
+            (ms(1400), vec![0xb0, 64, 0]),
+            (ms(1400), vec![0xb0, 123, 0]),
+            (ms(1400), vec![0xb9, 64, 0]),
+            (ms(1400), vec![0xb9, 123, 0]),
+            (ms(1450), vec![0x90, 62, 100]),
+            (ms(1650), vec![0x90, 62, 0]),
+        ]
+    );
+    assert_eq!(
+        player.set_tempo_scale(-0.5),
+        Err(MidiError::InvalidSetting {
+            field: "tempo scale",
+            value: "-0.5".to_string()
+        })
+    );
+}
+
+#[test]
+fn loop_and_mute() {
+    let smf = song();
+    let (mock, synth, mut player) = setup(&smf);
+    player.set_loop(Some(ms(500)..ms(1250))).unwrap();
+    player.set_muted(2, true).unwrap();
+    player.play().unwrap();
+    run(&mock, ms(2000), || {
+        player.update().unwrap();
+    });
+    // at the start of the loop for the second time
+    assert!(player.is_playing());
+    assert_eq!(player.position(), ms(500));
+    let release = |timestamp| {
+        [
+            vec![0x80, 62, 0],
+            vec![0xb0, 64, 0],
+            vec![0xb0, 123, 0],
+            vec![0xb9, 64, 0],
+            vec![0xb9, 123, 0],
+            vec![0x90, 60, 0],
+            // note off of the muted track
+            vec![0x99, 36, 0],
+        ]
+        .map(|data| (timestamp, data))
+    };
+    assert_eq!(
+        synth.take_sent_at(),
+        [
+            &[
+                (ms(1000), vec![0x90, 60, 100]),
+                (ms(1500), vec![0x90, 60, 0]),
+                (ms(1500), vec![0x99, 36, 0]),
+                (ms(2000), vec![0x90, 62, 100]),
+            ][..],
+            // released at the end of the loop, before continuing at its start
+            &release(ms(2250)),
+            &[(ms(2750), vec![0x90, 62, 100])],
+            &release(ms(3000)),
+        ]
+        .concat()
+    );
+
+    player.set_loop(None).unwrap();
+    player.set_muted(2, false).unwrap();
+    run(&mock, ms(1000), || {
+        player.update().unwrap();
+    });
+    assert!(!player.is_playing());
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![
+            (ms(3500), vec![0x90, 62, 100]),
+            (ms(3750), vec![0x90, 62, 0])
+        ]
+    );
+    assert_eq!(
+        player.set_muted(3, true),
+        Err(MidiError::InvalidSetting {
+            field: "track",
+            value: "3".to_string()
+        })
+    );
+}
+
+#[test]
+fn empty_escape() {
+    let smf = Smf {
+        format: Format::SingleTrack,
+        timing: Timing::Metrical(480),
+        tracks: vec![Track {
+            events: vec![
+                event(0, Event::Escape(vec![])),
+                event(480, Event::Escape(vec![0xfa])),
+                event(0, Event::Escape(vec![])),
+                end(0),
+            ],
+        }],
+    };
+    let (mock, synth, mut player) = setup(&smf);
+    player.play().unwrap();
+    run(&mock, ms(500), || {
+        player.update().unwrap();
+    });
+    assert!(!player.is_playing());
+    assert_eq!(synth.take_sent_at(), vec![(ms(1500), vec![0xfa])]);
+}
+
+#[test]
+fn timecode() {
+    // 25 frames per second of 40 ticks, 1 ms per tick
+    let smf = Smf {
+        format: Format::SingleTrack,
+        timing: Timing::Timecode {
+            fps: 25,
+            ticks_per_frame: 40,
+        },
+        tracks: vec![Track {
+            events: vec![
+                // ignored with timecode timing
+                event(0, Event::Meta(MetaEvent::Tempo(250_000))),
+                event(20, Event::Escape(vec![0xfa])),
+                event(30, Event::Escape(vec![0xfc])),
+                end(0),
+            ],
+        }],
+    };
+    let (mock, synth, mut player) = setup(&smf);
+    player.play().unwrap();
+    mock.advance(ms(50));
+    assert!(!player.update().unwrap());
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![(ms(1020), vec![0xfa]), (ms(1050), vec![0xfc])]
+    );
+    assert_eq!(
+        Player::new(
+            &ArcMutexMidiCon::with_backend(Box::new(mock)),
+            synth.id(),
+            &Smf {
+                format: Format::Sequential,
+                ..smf
+            }
+        )
+        .err(),
+        Some(MidiError::Unsupported("playing format 2"))
+    );
+}
+
+#[test]
+fn drop_frame_timecode() {
+    // 29.97 frames per second of 1 tick, 30 frames lasting 1.001 s
+    let smf = Smf {
+        format: Format::SingleTrack,
+        timing: Timing::Timecode {
+            fps: 29,
+            ticks_per_frame: 1,
+        },
+        tracks: vec![Track {
+            events: vec![event(30, Event::Escape(vec![0xfa])), end(0)],
+        }],
+    };
+    let (mock, synth, mut player) = setup(&smf);
+    assert_eq!(player.duration(), ms(1001));
+    player.play().unwrap();
+    run(&mock, ms(1100), || {
+        player.update().unwrap();
+    });
+    assert_eq!(synth.take_sent_at(), vec![(ms(2001), vec![0xfa])]);
+}
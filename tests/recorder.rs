@@ -0,0 +1,142 @@
+mod common;
+
+use rmidi::error::MidiError;
+use rmidi::message::MidiMessage;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+use rmidi::recorder::SmfRecorder;
+use rmidi::smf::*;
+
+use common::*;
+
+use std::time::Duration;
+
+fn note_on(channel: u8, note: u8) -> Event {
+    Event::Midi(MidiMessage::NoteOn {
+        channel,
+        note,
+        velocity: 100,
+    })
+}
+
+/// Record notes of two sources, for 2 quarter notes at 120 bpm.
+fn record(format: Format) -> Smf {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let pads = mock.add_source("Pads");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    mock.advance(Duration::from_secs(1));
+
+    // 480 ticks per quarter note at 120 bpm, 960 ticks per second
+    let recorder = SmfRecorder::new(format, 480, 500_000, midi_con.now()).unwrap();
+    assert_eq!(
+        midi_con.connect_source_by_index_recorder(0, &recorder),
+        Ok(keys.id())
+    );
+    midi_con
+        .connect_source_by_name_recorder("Pads", &recorder)
+        .unwrap();
+    keys.inject(&[0x90, 60, 100]);
+    mock.advance(Duration::from_millis(250));
+    // clock left out, tune request escaped
+    pads.inject(&[0x99, 36, 100, 0xf8, 0xf6, 0xfe]);
+    mock.advance(Duration::from_millis(250));
+    keys.inject(&[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]);
+    // a tick is 1.04 ms, rounded to the nearest tick
+    mock.advance(Duration::from_micros(1_300));
+    keys.inject(&[0x90, 64, 100]);
+    let smf = recorder.stop();
+    keys.inject(&[0x90, 67, 100]);
+    smf
+}
+
+#[test]
+fn simultaneous() {
+    let smf = record(Format::Simultaneous);
+    assert_eq!(smf.format, Format::Simultaneous);
+    assert_eq!(smf.timing, Timing::Metrical(480));
+    assert_eq!(
+        smf.tracks,
+        vec![
+            Track {
+                events: vec![event(0, Event::Meta(MetaEvent::Tempo(500_000))), end(0)]
+            },
+            Track {
+                events: vec![
+                    event(0, note_on(0, 60)),
+                    event(480, Event::SysEx(vec![0x7e, 0x7f, 0x06, 0x01, 0xf7])),
+                    event(1, note_on(0, 64)),
+                    end(0),
+                ]
+            },
+            Track {
+                events: vec![
+                    event(240, note_on(9, 36)),
+                    event(0, Event::Escape(vec![0xf6])),
+                    end(0),
+                ]
+            },
+        ]
+    );
+    assert_eq!(Smf::parse(&smf.to_bytes().unwrap()), Ok(smf));
+}
+
+#[test]
+fn single_track() {
+    let smf = record(Format::SingleTrack);
+    assert_eq!(
+        smf.tracks,
+        vec![Track {
+            events: vec![
+                event(0, Event::Meta(MetaEvent::Tempo(500_000))),
+                event(0, note_on(0, 60)),
+                event(240, note_on(9, 36)),
+                event(0, Event::Escape(vec![0xf6])),
+                event(240, Event::SysEx(vec![0x7e, 0x7f, 0x06, 0x01, 0xf7])),
+                event(1, note_on(0, 64)),
+                end(0),
+            ]
+        }]
+    );
+    assert_eq!(
+        SmfRecorder::new(Format::Sequential, 480, 500_000, Duration::ZERO).err(),
+        Some(MidiError::Unsupported("recording format 2"))
+    );
+    assert_eq!(
+        SmfRecorder::new(Format::SingleTrack, 0x8000, 500_000, Duration::ZERO).err(),
+        Some(MidiError::InvalidSetting {
+            field: "ticks per quarter note",
+            value: "32768".to_string()
+        })
+    );
+    assert_eq!(
+        SmfRecorder::new(Format::SingleTrack, 480, 0, Duration::ZERO).err(),
+        Some(MidiError::InvalidSetting {
+            field: "tempo",
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn realtime_and_long_gap() {
+    let mock = MockBackend::new();
+    let sync = mock.add_source("Sync");
+    let recorder = SmfRecorder::new(Format::SingleTrack, 480, 500_000, Duration::ZERO).unwrap();
+    recorder.set_record_realtime(true);
+    recorder.record(sync.id(), ms(500), &[0xfa, 0xf8]);
+    // longer than the largest delta time of a file, about 77 hours
+    recorder.record(sync.id(), Duration::from_secs(300_000), &[0xfc]);
+    let smf = recorder.stop();
+    assert_eq!(
+        smf.tracks[0].events,
+        vec![
+            event(0, Event::Meta(MetaEvent::Tempo(500_000))),
+            event(480, Event::Escape(vec![0xfa])),
+            event(0, Event::Escape(vec![0xf8])),
+            event(0x0fff_ffff - 480, Event::Escape(vec![0xfc])),
+            end(0),
+        ]
+    );
+    assert_eq!(Smf::parse(&smf.to_bytes().unwrap()), Ok(smf));
+}
@@ -0,0 +1,110 @@
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+/// Run `f` on another thread, failing on a deadlock rather than hanging.
+fn deadlock_free(f: impl FnOnce() + Send + 'static) {
+    let (done_tx, done_rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        f();
+        done_tx.send(()).unwrap();
+    });
+    match done_rx.recv_timeout(Duration::from_secs(5)) {
+        Ok(()) => handle.join().unwrap(),
+        // the thread panicked, report its panic
+        Err(mpsc::RecvTimeoutError::Disconnected) => handle.join().unwrap(),
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("deadlocked"),
+    }
+}
+
+#[test]
+fn notification_callback() {
+    deadlock_free(|| {
+        let mock = MockBackend::new();
+        let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+        let notifications = Arc::new(Mutex::new(vec![]));
+        let log = notifications.clone();
+        let mc = midi_con.clone();
+        midi_con.set_notification_callback(move |notification| {
+            log.lock().unwrap().push(notification.clone());
+            match notification {
+                Notification::SourceAdded { id, name, .. } if name == "Keys" => {
+                    assert_eq!(mc.list_sources().len(), 1);
+                    mc.connect_source(*id, |_, _, _| {}).unwrap();
+                    // notified again while handling this notification
+                    let echo = mc.create_virtual_source("Echo").unwrap();
+                    assert_eq!(mc.list_sources().len(), 2);
+                    drop(echo);
+                }
+                Notification::DestinationAdded { id, .. } => {
+                    mc.connect_destination(*id).unwrap();
+                    mc.set_notification_callback(|_| {});
+                }
+                _ => {}
+            }
+        });
+
+        let keys = mock.add_source("Keys");
+        let synth = mock.add_destination("Synth");
+        mock.add_destination("Pads");
+        assert_eq!(keys.connections(), 1);
+        assert_eq!(
+            midi_con.list_destinations(),
+            vec![
+                (synth.id(), true, "Synth".to_string()),
+                (EndpointId(synth.id().0 + 1), false, "Pads".to_string()),
+            ]
+        );
+        let notifications = notifications.lock().unwrap();
+        assert!(matches!(
+            notifications[..],
+            [
+                Notification::SourceAdded { .. },
+                Notification::SourceAdded { .. },
+                Notification::SetupChanged,
+                Notification::SourceRemoved { .. },
+                Notification::SetupChanged,
+                Notification::SetupChanged,
+                Notification::DestinationAdded { .. },
+                Notification::SetupChanged,
+            ]
+        ));
+    });
+}
+
+#[test]
+fn input_callback() {
+    deadlock_free(|| {
+        let mock = MockBackend::new();
+        let keys = mock.add_source("Keys");
+        let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+        // a virtual destination of the mock receives data while it is sent
+        let received = Arc::new(Mutex::new(vec![]));
+        let log = received.clone();
+        let keys_id = keys.id();
+        let looped = midi_con
+            .create_virtual_destination("Loop", move |_, data, mc| {
+                assert_eq!(mc.list_sources().len(), 1);
+                log.lock().unwrap().push(data.to_vec());
+                mc.disconnect_source(keys_id).unwrap();
+            })
+            .unwrap();
+        let looped_id = looped.id();
+        midi_con.connect_destination(looped_id).unwrap();
+        midi_con
+            .connect_source(keys.id(), move |_, data, mc| {
+                mc.send(looped_id, data).unwrap();
+                mc.flush(looped_id).unwrap();
+            })
+            .unwrap();
+
+        keys.inject(&[0x90, 60, 100]);
+        keys.inject(&[0x80, 60, 0]);
+        assert_eq!(*received.lock().unwrap(), vec![vec![0x90, 60, 100]]);
+        assert_eq!(keys.connections(), 0);
+    });
+}
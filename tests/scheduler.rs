@@ -0,0 +1,93 @@
+use rmidi::backend::*;
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Mock backend in real time, without native scheduling of output.
+struct RealTimeBackend {
+    mock: MockBackend,
+    epoch: Instant,
+}
+
+struct Output(Box<dyn OutputConnection>);
+
+impl OutputConnection for Output {
+    fn send(&mut self, data: &[u8]) -> Result<(), MidiError> {
+        self.0.send(data)
+    }
+}
+
+impl MidiBackend for RealTimeBackend {
+    fn sources(&self) -> Vec<(EndpointId, String)> {
+        self.mock.sources()
+    }
+
+    fn destinations(&self) -> Vec<(EndpointId, String)> {
+        self.mock.destinations()
+    }
+
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn set_notification_handler(&mut self, handler: NotificationHandler) {
+        self.mock.set_notification_handler(handler)
+    }
+
+    fn open_input(
+        &mut self,
+        source: EndpointId,
+        callback: InputCallback,
+    ) -> Result<Box<dyn InputConnection>, MidiError> {
+        self.mock.open_input(source, callback)
+    }
+
+    fn open_output(
+        &mut self,
+        destination: EndpointId,
+    ) -> Result<Box<dyn OutputConnection>, MidiError> {
+        Ok(Box::new(Output(self.mock.open_output(destination)?)))
+    }
+}
+
+#[test]
+fn software_scheduling() {
+    let mock = MockBackend::new();
+    let synth = mock.add_destination("Synth");
+    let drums = mock.add_destination("Drums");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(RealTimeBackend {
+        mock: mock.clone(),
+        epoch: Instant::now(),
+    }));
+    midi_con.connect_destination(synth.id()).unwrap();
+    midi_con.connect_destination(drums.id()).unwrap();
+
+    let now = midi_con.now();
+    for (delay, data) in [(200, 0xfc), (100, 0xfa), (100, 0xf8)] {
+        midi_con
+            .send_at(synth.id(), now + Duration::from_millis(delay), &[data])
+            .unwrap();
+    }
+    midi_con
+        .send_at(drums.id(), now + Duration::from_millis(100), &[0xfa])
+        .unwrap();
+    midi_con.flush(drums.id()).unwrap();
+    assert!(synth.sent().is_empty());
+
+    thread::sleep(Duration::from_millis(500));
+    // in order of timestamp, and of scheduling for equal timestamps
+    assert_eq!(synth.sent(), vec![vec![0xfa], vec![0xf8], vec![0xfc]]);
+    assert!(drums.sent().is_empty());
+
+    // scheduled data does not keep the connection alive
+    midi_con
+        .send_at(synth.id(), now + Duration::from_secs(10), &[0xfe])
+        .unwrap();
+    let weak = Arc::downgrade(&midi_con.0);
+    drop(midi_con);
+    assert!(weak.upgrade().is_none());
+}
@@ -0,0 +1,108 @@
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+use rmidi::session::*;
+
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn snapshot_round_trip() {
+    let mock = MockBackend::new();
+    mock.add_source("Keys");
+    let pads = mock.add_source("Pads");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_source(pads.id(), |_, _, _| {}).unwrap();
+    midi_con.connect_destination(synth.id()).unwrap();
+
+    let config = midi_con.snapshot();
+    assert_eq!(
+        config,
+        SessionConfig {
+            sources: vec![EndpointConfig {
+                id: pads.id(),
+                name: "Pads".to_string()
+            }],
+            destinations: vec![EndpointConfig {
+                id: synth.id(),
+                name: "Synth".to_string()
+            }],
+        }
+    );
+
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(
+        serde_json::from_str::<SessionConfig>(&json).unwrap(),
+        config
+    );
+    let toml = toml::to_string(&config).unwrap();
+    assert_eq!(toml::from_str::<SessionConfig>(&toml).unwrap(), config);
+
+    // ids beyond the range of TOML integers
+    let config = SessionConfig {
+        sources: vec![EndpointConfig {
+            id: EndpointId(u64::MAX),
+            name: "Keys".to_string(),
+        }],
+        destinations: vec![],
+    };
+    let toml = toml::to_string(&config).unwrap();
+    assert_eq!(toml::from_str::<SessionConfig>(&toml).unwrap(), config);
+}
+
+#[test]
+fn restore() {
+    let config = SessionConfig {
+        sources: vec![
+            EndpointConfig {
+                id: EndpointId(1000),
+                name: "Keys".to_string(),
+            },
+            EndpointConfig {
+                id: EndpointId(1001),
+                name: "Pads".to_string(),
+            },
+        ],
+        destinations: vec![EndpointConfig {
+            id: EndpointId(1002),
+            name: "Synth".to_string(),
+        }],
+    };
+
+    // a new session, with other ids and without pads
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    let report = midi_con.restore(&config, |source| {
+        let log = log.clone();
+        let name = source.name.clone();
+        Box::new(move |_, data, _| log.lock().unwrap().push((name.clone(), data.to_vec())))
+    });
+    assert_eq!(
+        report,
+        RestoreReport {
+            missing_sources: vec![config.sources[1].clone()],
+            ..Default::default()
+        }
+    );
+    assert!(!report.is_complete());
+
+    assert_eq!(keys.connections(), 1);
+    keys.inject(&[0xfa]);
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![("Keys".to_string(), vec![0xfa])]
+    );
+    midi_con.send(synth.id(), &[0xfc]).unwrap();
+    assert_eq!(synth.take_sent(), vec![vec![0xfc]]);
+
+    // restoring again leaves the connections as they are
+    assert_eq!(
+        midi_con.restore(&midi_con.snapshot(), |_| Box::new(|_, _, _| {})),
+        RestoreReport::default()
+    );
+    assert_eq!(keys.connections(), 1);
+}
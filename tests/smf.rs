@@ -0,0 +1,298 @@
+mod common;
+
+use rmidi::error::MidiError;
+use rmidi::message::MidiMessage;
+use rmidi::smf::*;
+
+use common::*;
+
+/// A file of `format` with the given `division` and chunks.
+fn file(format: u16, division: [u8; 2], chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let track_count = chunks.iter().filter(|(kind, _)| *kind == b"MTrk").count() as u16;
+    let mut data = b"MThd\0\0\0\x06".to_vec();
+    data.extend_from_slice(&format.to_be_bytes());
+    data.extend_from_slice(&track_count.to_be_bytes());
+    data.extend_from_slice(&division);
+    for (kind, chunk) in chunks {
+        data.extend_from_slice(*kind);
+        data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        data.extend_from_slice(chunk);
+    }
+    data
+}
+
+fn invalid(offset: usize, reason: &'static str) -> MidiError {
+    MidiError::InvalidFile { offset, reason }
+}
+
+const TEMPO_TRACK: &[u8] = &[
+    0x00, 0xff, 0x03, 0x04, b'S', b'o', b'n', b'g', // track name
+    0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, // 120 bpm
+    0x00, 0xff, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08, // 6/8
+    0x00, 0xff, 0x59, 0x02, 0xfe, 0x01, // g minor
+    0x81, 0x00, 0xff, 0x06, 0x01, b'A', // marker, 128 ticks later
+    0x00, 0xff, 0x60, 0x01, 0x2a, // unknown
+    0x00, 0xff, 0x2f, 0x00,
+];
+
+const MUSIC_TRACK: &[u8] = &[
+    0x00, 0xc9, 0x05, // program change
+    0x00, 0x99, 36, 100, // note on
+    0x10, 38, 90, // running status
+    0x00, 0xf0, 0x03, 0x43, 0x12, 0x00, // SysEx, continued
+    0x08, 0xf7, 0x02, 0x34, 0xf7, // and its end
+    0x00, 0xf7, 0x01, 0xf8, // escaped clock
+    0x7f, 0x89, 36, 0, // note off
+    0x83, 0xff, 0xff, 0x7f, 0xe0, 0x00, 0x40, // pitch bend, long delta
+    0x00, 0xff, 0x2f, 0x00,
+];
+
+#[test]
+fn parse() {
+    let smf = Smf::parse(&file(
+        1,
+        [0x01, 0xe0],
+        &[
+            (b"MTrk", TEMPO_TRACK),
+            (b"XFIH", &[1, 2, 3]),
+            (b"MTrk", MUSIC_TRACK),
+        ],
+    ))
+    .unwrap();
+    assert_eq!(smf.format, Format::Simultaneous);
+    assert_eq!(smf.timing, Timing::Metrical(480));
+    assert_eq!(
+        smf.tracks,
+        vec![
+            Track {
+                events: vec![
+                    event(0, Event::Meta(MetaEvent::TrackName(b"Song".to_vec()))),
+                    event(0, Event::Meta(MetaEvent::Tempo(500_000))),
+                    event(
+                        0,
+                        Event::Meta(MetaEvent::TimeSignature {
+                            numerator: 6,
+                            denominator: 3,
+                            clocks_per_click: 24,
+                            thirty_seconds_per_quarter: 8
+                        })
+                    ),
+                    event(
+                        0,
+                        Event::Meta(MetaEvent::KeySignature {
+                            sharps: -2,
+                            minor: true
+                        })
+                    ),
+                    event(128, Event::Meta(MetaEvent::Marker(b"A".to_vec()))),
+                    event(
+                        0,
+                        Event::Meta(MetaEvent::Unknown {
+                            kind: 0x60,
+                            data: vec![0x2a]
+                        })
+                    ),
+                    event(0, Event::Meta(MetaEvent::EndOfTrack)),
+                ]
+            },
+            Track {
+                events: vec![
+                    event(
+                        0,
+                        Event::Midi(MidiMessage::ProgramChange {
+                            channel: 9,
+                            program: 5
+                        })
+                    ),
+                    event(
+                        0,
+                        Event::Midi(MidiMessage::NoteOn {
+                            channel: 9,
+                            note: 36,
+                            velocity: 100
+                        })
+                    ),
+                    event(
+                        16,
+                        Event::Midi(MidiMessage::NoteOn {
+                            channel: 9,
+                            note: 38,
+                            velocity: 90
+                        })
+                    ),
+                    event(0, Event::SysEx(vec![0x43, 0x12, 0x00])),
+                    event(8, Event::Escape(vec![0x34, 0xf7])),
+                    event(0, Event::Escape(vec![0xf8])),
+                    event(
+                        127,
+                        Event::Midi(MidiMessage::NoteOff {
+                            channel: 9,
+                            note: 36,
+                            velocity: 0
+                        })
+                    ),
+                    event(
+                        0x7f_ffff,
+                        Event::Midi(MidiMessage::PitchBend {
+                            channel: 0,
+                            value: 0x2000
+                        })
+                    ),
+                    event(0, Event::Meta(MetaEvent::EndOfTrack)),
+                ]
+            },
+        ]
+    );
+    let music = &smf.tracks[1].events;
+    assert_eq!(
+        music[3].event.midi_data(),
+        Some(vec![0xf0, 0x43, 0x12, 0x00])
+    );
+    assert_eq!(music[4].event.midi_data(), Some(vec![0x34, 0xf7]));
+    assert_eq!(music[6].event.midi_data(), Some(vec![0x89, 36, 0]));
+    assert_eq!(smf.tracks[0].events[1].event.midi_data(), None);
+}
+
+#[test]
+fn write() {
+    let data = file(
+        1,
+        [0x01, 0xe0],
+        &[(b"MTrk", TEMPO_TRACK), (b"MTrk", MUSIC_TRACK)],
+    );
+    let smf = Smf::parse(&data).unwrap();
+    assert_eq!(smf.to_bytes().unwrap(), data);
+
+    // end of track is appended
+    let mut smf = Smf {
+        format: Format::SingleTrack,
+        timing: Timing::Timecode {
+            fps: 30,
+            ticks_per_frame: 80,
+        },
+        tracks: vec![Track {
+            events: vec![event(
+                0x4000,
+                Event::Midi(MidiMessage::SysEx(vec![0x7e, 0x7f, 0x09, 0x01])),
+            )],
+        }],
+    };
+    let end: &[u8] = &[0x00, 0xff, 0x2f, 0x00];
+    let track = [
+        &[
+            0x81, 0x80, 0x00, 0xf7, 0x06, 0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7,
+        ],
+        end,
+    ]
+    .concat();
+    assert_eq!(
+        smf.to_bytes().unwrap(),
+        file(0, [0xe2, 80], &[(b"MTrk", &track)])
+    );
+
+    smf.tracks.push(Track::default());
+    assert_eq!(
+        smf.to_bytes(),
+        Err(MidiError::InvalidMessage {
+            field: "track count",
+            value: 2
+        })
+    );
+    smf.format = Format::Sequential;
+    smf.tracks[1]
+        .events
+        .push(event(0, Event::Meta(MetaEvent::Tempo(1 << 24))));
+    assert_eq!(
+        smf.to_bytes(),
+        Err(MidiError::InvalidMessage {
+            field: "tempo",
+            value: 1 << 24
+        })
+    );
+}
+
+#[test]
+fn timecode() {
+    let track: &[u8] = &[0x00, 0xff, 0x2f, 0x00];
+    let smf = Smf::parse(&file(0, [0xe7, 40], &[(b"MTrk", track)])).unwrap();
+    assert_eq!(
+        smf.timing,
+        Timing::Timecode {
+            fps: 25,
+            ticks_per_frame: 40
+        }
+    );
+    assert_eq!(
+        Smf::parse(&file(0, [0xe0, 40], &[(b"MTrk", track)])),
+        Err(invalid(12, "unknown SMPTE format"))
+    );
+}
+
+#[test]
+fn errors() {
+    let end: &[u8] = &[0x00, 0xff, 0x2f, 0x00];
+    let track = |data: &[u8]| file(0, [0, 96], &[(b"MTrk", data)]);
+    // the first track event is at byte 22
+    let cases = [
+        (b"RIFF".to_vec(), invalid(0, "missing MThd header")),
+        (
+            file(0, [0, 96], &[(b"MTrk", end)])[..16].to_vec(),
+            invalid(16, "truncated chunk header"),
+        ),
+        (
+            file(0, [0, 96], &[(b"MTrk", end)])[..24].to_vec(),
+            invalid(24, "truncated chunk"),
+        ),
+        (
+            file(3, [0, 96], &[(b"MTrk", end)]),
+            invalid(8, "unknown format"),
+        ),
+        (
+            file(0, [0, 96], &[(b"MTrk", end), (b"MTrk", end)]),
+            invalid(10, "format 0 with other than one track"),
+        ),
+        (
+            {
+                let mut data = file(1, [0, 96], &[(b"MTrk", end)]);
+                data[11] = 2;
+                data
+            },
+            invalid(26, "missing track"),
+        ),
+        (
+            track(&[0x00, 0x90, 60, 100]),
+            invalid(26, "missing end of track"),
+        ),
+        (
+            track(&[0x00, 60, 100, 0x00, 0xff, 0x2f, 0x00]),
+            invalid(23, "data byte without running status"),
+        ),
+        (
+            track(&[0x00, 0xff, 0x01, 0x20, b'a', 0x00, 0xff, 0x2f, 0x00]),
+            invalid(31, "truncated event"),
+        ),
+        (
+            track(&[0x00, 0x90, 60, 0x80, 0x00, 0xff, 0x2f, 0x00]),
+            invalid(25, "invalid data byte"),
+        ),
+        (
+            track(&[0x00, 0xf8, 0x00, 0xff, 0x2f, 0x00]),
+            invalid(23, "invalid status byte"),
+        ),
+        (
+            track(&[0x80, 0x80, 0x80, 0x80, 0x00, 0xff, 0x2f, 0x00]),
+            invalid(22, "variable length quantity longer than 4 bytes"),
+        ),
+        (
+            track(&[0x00, 0xff, 0x51, 0x02, 0x07, 0xa1, 0x00, 0xff, 0x2f, 0x00]),
+            invalid(23, "invalid meta event"),
+        ),
+        (
+            track(&[0x00, 0xff, 0x2f, 0x00, 0x00, 0x90, 60, 100]),
+            invalid(26, "event after end of track"),
+        ),
+    ];
+    for (data, error) in cases {
+        assert_eq!(Smf::parse(&data), Err(error));
+    }
+}
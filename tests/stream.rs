@@ -0,0 +1,72 @@
+use futures_lite::{StreamExt, future};
+use rmidi::channel::MidiEvent;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::time::Duration;
+
+#[test]
+fn source_stream() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let (id, mut stream) = midi_con.source_stream_by_index(0).unwrap();
+    assert_eq!(id, keys.id());
+    keys.inject(&[0x90, 60, 100]);
+    mock.advance(Duration::from_millis(5));
+    keys.inject(&[0x80, 60, 0]);
+    assert_eq!(
+        future::block_on(stream.next()),
+        Some(MidiEvent {
+            source: keys.id(),
+            timestamp: Duration::ZERO,
+            data: vec![0x90, 60, 100].into()
+        })
+    );
+    assert_eq!(
+        future::block_on(stream.next()).unwrap().timestamp,
+        Duration::from_millis(5)
+    );
+}
+
+#[test]
+fn notifications() {
+    let mock = MockBackend::new();
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let mut first = midi_con.notifications();
+    let mut second = midi_con.notifications();
+    let keys = mock.add_source("Keys");
+    let expected = Notification::SourceAdded {
+        id: keys.id(),
+        index: 0,
+        name: "Keys".to_string(),
+    };
+    for stream in [&mut first, &mut second] {
+        assert_eq!(future::block_on(stream.next()), Some(expected.clone()));
+        assert_eq!(
+            future::block_on(stream.next()),
+            Some(Notification::SetupChanged)
+        );
+    }
+
+    // dropped streams are no longer notified
+    drop(second);
+    keys.unplug();
+    assert!(matches!(
+        future::block_on(first.next()),
+        Some(Notification::SourceRemoved { .. })
+    ));
+}
+
+#[test]
+fn send_async() {
+    let mock = MockBackend::new();
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_destination(synth.id()).unwrap();
+
+    future::block_on(midi_con.send_async(synth.id(), &[0x90, 60, 100])).unwrap();
+    assert_eq!(synth.take_sent(), vec![vec![0x90, 60, 100]]);
+}
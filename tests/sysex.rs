@@ -0,0 +1,213 @@
+mod common;
+
+use rmidi::error::MidiError;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+use rmidi::sysex::*;
+
+use common::ms;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Delivered = Vec<(Duration, Vec<u8>)>;
+
+fn push(
+    assembler: &mut SysExAssembler,
+    timestamp: Duration,
+    data: &[u8],
+) -> (Delivered, Vec<MidiError>) {
+    let mut delivered = vec![];
+    let mut errors = vec![];
+    assembler.push(
+        timestamp,
+        data,
+        |timestamp, data| delivered.push((timestamp, data.to_vec())),
+        |error| errors.push(error),
+    );
+    (delivered, errors)
+}
+
+#[test]
+fn reassemble() {
+    let mut assembler = SysExAssembler::new(SysExLimits::default());
+    assert_eq!(
+        push(&mut assembler, ms(1), &[0x90, 60, 100, 0xf0, 0x7e]),
+        (vec![(ms(1), vec![0x90, 60, 100])], vec![])
+    );
+    // realtime bytes are passed on right away
+    assert_eq!(
+        push(&mut assembler, ms(2), &[0x7f, 0xf8, 0x06]),
+        (vec![(ms(2), vec![0xf8])], vec![])
+    );
+    assert_eq!(
+        push(&mut assembler, ms(3), &[0x01, 0xf7, 0x80, 60, 0]),
+        (
+            vec![
+                (ms(1), vec![0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]),
+                (ms(3), vec![0x80, 60, 0]),
+            ],
+            vec![]
+        )
+    );
+}
+
+#[test]
+fn dropped() {
+    let mut assembler = SysExAssembler::new(SysExLimits {
+        max_size: 4,
+        timeout: Some(ms(10)),
+    });
+
+    // interrupted by a note on
+    assert_eq!(
+        push(&mut assembler, ms(0), &[0xf0, 0x43, 0x90, 60, 100]),
+        (
+            vec![(ms(0), vec![0x90, 60, 100])],
+            vec![MidiError::SysExInterrupted]
+        )
+    );
+
+    // too long, the rest of the message is dropped
+    assert_eq!(
+        push(&mut assembler, ms(1), &[0xf0, 1, 2, 3, 4, 0xf7, 0xfe]),
+        (vec![(ms(1), vec![0xfe])], vec![MidiError::SysExTooLong(4)])
+    );
+    assert_eq!(
+        push(&mut assembler, ms(2), &[0xf0, 1, 2, 0xf7]),
+        (vec![(ms(2), vec![0xf0, 1, 2, 0xf7])], vec![])
+    );
+
+    // timed out, the late piece is dropped
+    assert_eq!(push(&mut assembler, ms(3), &[0xf0, 1]), (vec![], vec![]));
+    assert_eq!(
+        push(&mut assembler, ms(20), &[2, 0xf7, 0xf6]),
+        (vec![(ms(20), vec![0xf6])], vec![MidiError::SysExTimeout])
+    );
+}
+
+#[test]
+fn input_errors() {
+    let mock = MockBackend::new();
+    let keys = mock.add_source("Keys");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let errors = Arc::new(Mutex::new(vec![]));
+    let log = errors.clone();
+    midi_con.set_notification_callback(move |notification| {
+        if let Notification::InputError { id, error } = notification {
+            log.lock().unwrap().push((*id, error.clone()));
+        }
+    });
+    midi_con.set_sysex_limits(SysExLimits {
+        max_size: 16,
+        timeout: None,
+    });
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    midi_con
+        .connect_source(keys.id(), move |timestamp, data, _| {
+            log.lock().unwrap().push((timestamp, data.to_vec()))
+        })
+        .unwrap();
+
+    keys.inject(&[0xf0, 0x7e]);
+    mock.advance(ms(5));
+    keys.inject(&[0x7f, 0xf7]);
+    keys.inject(&[0xf0; 2]);
+    keys.inject(&[0; 20]);
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![(ms(0), vec![0xf0, 0x7e, 0x7f, 0xf7])]
+    );
+    assert_eq!(
+        *errors.lock().unwrap(),
+        vec![
+            (keys.id(), MidiError::SysExInterrupted),
+            (keys.id(), MidiError::SysExTooLong(16)),
+        ]
+    );
+}
+
+#[test]
+fn send_sysex() {
+    let mock = MockBackend::new();
+    let synth = mock.add_destination("Synth");
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    midi_con.connect_destination(synth.id()).unwrap();
+
+    midi_con
+        .send_sysex(synth.id(), &[1, 2, 3, 4, 5], 3, None)
+        .unwrap();
+    assert_eq!(
+        synth.take_sent(),
+        vec![vec![0xf0, 1, 2], vec![3, 4, 5], vec![0xf7]]
+    );
+
+    mock.advance(ms(100));
+    midi_con
+        .send_sysex(synth.id(), &[1, 2, 3, 4], 4, Some(ms(20)))
+        .unwrap();
+    assert_eq!(
+        synth.take_sent_at(),
+        vec![(ms(100), vec![0xf0, 1, 2, 3]), (ms(120), vec![4, 0xf7])]
+    );
+
+    assert_eq!(
+        midi_con.send_sysex(synth.id(), &[0x80], 4, None),
+        Err(MidiError::InvalidMessage {
+            field: "SysEx data",
+            value: 0x80
+        })
+    );
+    assert!(synth.sent().is_empty());
+}
+
+fn split(splitter: &mut SysExSplitter, data: &[u8]) -> Vec<(bool, Vec<u8>)> {
+    splitter
+        .split(data)
+        .map(|piece| match piece {
+            Piece::SysEx(data) => (true, data.to_vec()),
+            Piece::Other(data) => (false, data.to_vec()),
+        })
+        .collect()
+}
+
+#[test]
+fn splitter() {
+    let mut splitter = SysExSplitter::new();
+    assert_eq!(
+        split(
+            &mut splitter,
+            &[0x90, 60, 100, 0xf0, 0x7e, 0xf7, 0x80, 60, 0]
+        ),
+        vec![
+            (false, vec![0x90, 60, 100]),
+            (true, vec![0xf0, 0x7e, 0xf7]),
+            (false, vec![0x80, 60, 0]),
+        ]
+    );
+
+    // split over several chunks, with an interleaved clock tick
+    assert_eq!(
+        split(&mut splitter, &[0xf0, 0x7e, 0x7f]),
+        vec![(true, vec![0xf0, 0x7e, 0x7f])]
+    );
+    assert_eq!(
+        split(&mut splitter, &[0x06, 0xf8, 0x01]),
+        vec![(true, vec![0x06]), (false, vec![0xf8]), (true, vec![0x01])]
+    );
+    assert_eq!(
+        split(&mut splitter, &[0xf7, 0xfe]),
+        vec![(true, vec![0xf7]), (false, vec![0xfe])]
+    );
+
+    // interrupted by another status byte
+    split(&mut splitter, &[0xf0, 0x7e]);
+    assert_eq!(
+        split(&mut splitter, &[0x90, 60, 100]),
+        vec![(false, vec![0x90, 60, 100])]
+    );
+    assert_eq!(split(&mut splitter, &[0x7e]), vec![(false, vec![0x7e])]);
+    assert_eq!(split(&mut splitter, &[]), vec![]);
+}
@@ -0,0 +1,99 @@
+use rmidi::message::MidiMessage;
+use rmidi::midi_con::*;
+use rmidi::mock::MockBackend;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn virtual_source() {
+    let mock = MockBackend::new();
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+    let notifications = Arc::new(Mutex::new(vec![]));
+    let log = notifications.clone();
+    midi_con.set_notification_callback(move |notification| {
+        log.lock().unwrap().push(notification.clone())
+    });
+
+    let mut source = midi_con.create_virtual_source("Out").unwrap();
+    assert_eq!(source.name(), "Out");
+    assert_eq!(
+        midi_con.list_sources(),
+        vec![(source.id(), false, "Out".to_string())]
+    );
+
+    // loop back to the connection itself, as another application would connect
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    midi_con
+        .connect_source(source.id(), move |_, data, _| {
+            log.lock().unwrap().push(data.to_vec())
+        })
+        .unwrap();
+    source.send(&[0x90, 60, 100]).unwrap();
+    source
+        .send_message(&MidiMessage::note_on(1, 60, 0).unwrap())
+        .unwrap();
+    assert!(source.send_message(&MidiMessage::SongSelect(128)).is_err());
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![vec![0x90, 60, 100], vec![0x90, 60, 0]]
+    );
+
+    let id = source.id();
+    drop(source);
+    assert!(midi_con.list_sources().is_empty());
+    assert_eq!(
+        *notifications.lock().unwrap(),
+        vec![
+            Notification::SourceAdded {
+                id,
+                index: 0,
+                name: "Out".to_string()
+            },
+            Notification::SetupChanged,
+            Notification::SourceRemoved {
+                id,
+                index: 0,
+                name: "Out".to_string()
+            },
+            Notification::SetupChanged,
+        ]
+    );
+}
+
+#[test]
+fn virtual_destination() {
+    let mock = MockBackend::new();
+    let midi_con = ArcMutexMidiCon::with_backend(Box::new(mock.clone()));
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    let destination = midi_con
+        .create_virtual_destination("In", move |timestamp, data, _| {
+            log.lock().unwrap().push((timestamp, data.to_vec()))
+        })
+        .unwrap();
+    assert_eq!(destination.name(), "In");
+    assert_eq!(
+        midi_con.list_destinations(),
+        vec![(destination.id(), false, "In".to_string())]
+    );
+
+    // SysEx is reassembled as for connected sources
+    midi_con.connect_destination(destination.id()).unwrap();
+    mock.advance(Duration::from_millis(10));
+    midi_con.send(destination.id(), &[0xf0, 0x7e]).unwrap();
+    midi_con.send(destination.id(), &[0x7f, 0xf7]).unwrap();
+    midi_con.send(destination.id(), &[0xfa]).unwrap();
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![
+            (Duration::from_millis(10), vec![0xf0, 0x7e, 0x7f, 0xf7]),
+            (Duration::from_millis(10), vec![0xfa]),
+        ]
+    );
+
+    drop(destination);
+    assert!(midi_con.list_destinations().is_empty());
+}